@@ -0,0 +1,72 @@
+//! Debug builds swap in an allocator that aborts when the audio thread allocates
+//! or frees memory while rendering, since either can block for as long as the
+//! system allocator likes and cause dropouts. Release builds use the system
+//! allocator as is and the check costs nothing.
+
+#[cfg(debug_assertions)]
+mod checked {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        // Const initialized, so reading it from inside the allocator can't allocate
+        static FORBIDDEN: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub struct CheckedAllocator;
+
+    impl CheckedAllocator {
+        fn check(&self) {
+            let forbidden = FORBIDDEN.try_with(|f| f.replace(false)).unwrap_or(false);
+            if forbidden {
+                // Unwinding out of the allocator isn't allowed, and the flag is cleared
+                // first so printing is free to allocate
+                eprintln!("Heap allocation on the audio thread while rendering");
+                std::process::abort();
+            }
+        }
+    }
+
+    unsafe impl GlobalAlloc for CheckedAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.check();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.check();
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            self.check();
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            self.check();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CheckedAllocator = CheckedAllocator;
+
+    pub fn assert_no_alloc<T, F: FnOnce() -> T>(f: F) -> T {
+        let previous = FORBIDDEN.with(|forbidden| forbidden.replace(true));
+        let result = f();
+        FORBIDDEN.with(|forbidden| forbidden.set(previous));
+        result
+    }
+}
+
+/// Runs f, aborting in debug builds if it allocates or frees anything on the heap
+#[cfg(debug_assertions)]
+pub use checked::assert_no_alloc;
+
+/// Runs f, aborting in debug builds if it allocates or frees anything on the heap
+#[cfg(not(debug_assertions))]
+#[inline(always)]
+pub fn assert_no_alloc<T, F: FnOnce() -> T>(f: F) -> T {
+    f()
+}
@@ -0,0 +1,26 @@
+use thiserror::Error;
+
+use ravetable_engine::EngineError;
+
+#[derive(Debug, Error)]
+pub enum RavetableError {
+    #[error("No default output device available")]
+    NoDefaultDevice,
+    #[error("No output device named \"{0}\"")]
+    DeviceNotFound(String),
+    #[error("JACK host unavailable, make sure --features jack is specified")]
+    JackUnavailable,
+    #[error("Audio host unavailable: {0}")]
+    HostUnavailable(#[from] cpal::HostUnavailable),
+    #[error("Failed to list output devices: {0}")]
+    Devices(#[from] cpal::DevicesError),
+    #[error("Failed to get the output config: {0}")]
+    DeviceConfig(#[from] cpal::DefaultStreamConfigError),
+    #[error("Failed to open the output stream: {0}")]
+    BuildStream(#[from] cpal::BuildStreamError),
+    #[error("Failed to start the output stream: {0}")]
+    PlayStream(#[from] cpal::PlayStreamError),
+
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+}
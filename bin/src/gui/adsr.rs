@@ -1,8 +1,8 @@
 use tuix::*;
 
 use effects::adsr::ADSR;
+use ravetable_engine::params::{EnvelopeParam, ParamId};
 
-use crate::messages::EnvelopeParams;
 use crate::{
     gui::core_ui::audio_slider::AudioSlider, gui::core_ui::audio_widget::AudioWidgetContainer,
     gui::core_ui::hdivider::HDivider, gui::events::SynthControlEvent,
@@ -16,7 +16,6 @@ pub struct ADSRControls {
 
 impl ADSRControls {
     pub fn new(osc_id: usize, adsr: ADSR) -> Self {
-        let adsr = ADSR::convert_adsr_from_sample_clock(&adsr);
         ADSRControls { osc_id, adsr }
     }
 }
@@ -33,50 +32,38 @@ impl Widget for ADSRControls {
         let row = HBox::new().build(state, container, |builder| {
             builder
                 .set_justify_content(JustifyContent::SpaceEvenly)
-                .set_height(Units::Pixels(150.))
+                .set_height(Units::Pixels(275.))
                 .set_flex_direction(FlexDirection::Column)
         });
 
-        let slider_max = 10.;
-        AudioSlider::new("Attack", 0., slider_max, self.adsr.attack)
-            .on_change(move |val| {
-                let val = ADSR::convert_value_from_time(val);
-                Event::new(SynthControlEvent::Envelope(id, EnvelopeParams::Attack(val)))
-            })
-            .build(state, row, |builder| builder);
-        AudioSlider::new("Decay", 0., slider_max, self.adsr.decay)
-            .on_change(move |val| {
-                let val = ADSR::convert_value_from_time(val);
-                Event::new(SynthControlEvent::Envelope(id, EnvelopeParams::Decay(val)))
-            })
-            .build(state, row, |builder| builder);
-        AudioSlider::new("Sustain", 0., slider_max, self.adsr.sustain)
-            .on_change(move |val| {
-                let val = ADSR::convert_value_from_time(val);
-                Event::new(SynthControlEvent::Envelope(
-                    id,
-                    EnvelopeParams::Sustain(val),
-                ))
-            })
-            .build(state, row, |builder| builder);
-        AudioSlider::new("Release", 0., slider_max, self.adsr.release)
-            .on_change(move |val| {
-                let val = ADSR::convert_value_from_time(val);
-                Event::new(SynthControlEvent::Envelope(
-                    id,
-                    EnvelopeParams::Release(val),
-                ))
-            })
-            .build(state, row, |builder| builder);
+        let slider = |param: EnvelopeParam| {
+            let info = param.info();
+            AudioSlider::new(info.name, info.min, info.max, param.get(&self.adsr)).on_change(
+                move |val| {
+                    Event::new(SynthControlEvent::SetParam(
+                        ParamId::Envelope(id, param),
+                        val,
+                    ))
+                },
+            )
+        };
+        slider(EnvelopeParam::Attack).build(state, row, |builder| builder);
+        slider(EnvelopeParam::Decay).build(state, row, |builder| builder);
+        slider(EnvelopeParam::Sustain).build(state, row, |builder| builder);
+        slider(EnvelopeParam::Release).build(state, row, |builder| builder);
 
         HDivider::new().build(state, row, |builder| builder);
 
-        AudioSlider::new("Delay", 0., slider_max, self.adsr.delay)
-            .on_change(move |val| {
-                let val = ADSR::convert_value_from_time(val);
-                Event::new(SynthControlEvent::Envelope(id, EnvelopeParams::Delay(val)))
-            })
-            .build(state, row, |builder| builder);
+        slider(EnvelopeParam::Delay).build(state, row, |builder| builder);
+        slider(EnvelopeParam::Hold).build(state, row, |builder| builder);
+
+        HDivider::new().build(state, row, |builder| builder);
+
+        slider(EnvelopeParam::AttackCurve).build(state, row, |builder| builder);
+        slider(EnvelopeParam::DecayCurve).build(state, row, |builder| builder);
+        slider(EnvelopeParam::ReleaseCurve).build(state, row, |builder| builder);
+        slider(EnvelopeParam::VelocityToLevel).build(state, row, |builder| builder);
+        slider(EnvelopeParam::VelocityToTime).build(state, row, |builder| builder);
 
         entity
     }
@@ -1,9 +1,24 @@
-use crate::messages::{EnvelopeParams, ModulatedFilterParams, OscParams};
+use std::path::PathBuf;
+
+use ravetable_engine::params::ParamId;
+use ravetable_engine::synths::Sample;
 
-// id, value
 #[derive(PartialEq, Clone, Debug)]
 pub enum SynthControlEvent {
-    OscillatorControl(usize, OscParams),
-    ModulatedFilter(usize, usize, ModulatedFilterParams),
-    Envelope(usize, EnvelopeParams),
+    // id, value
+    SetParam(ParamId, f32),
+    // osc_id, wavetable
+    SampleChange(usize, Sample),
+}
+
+// Shown on the widget it's sent to
+#[derive(PartialEq, Clone, Debug)]
+pub enum ErrorEvent {
+    Wavetable(String),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum PresetEvent {
+    Save,
+    Load(PathBuf),
 }
@@ -1,14 +1,11 @@
 use tuix::*;
 
-use effects::filters::ModulatedFilterStatePacket;
-
-use crate::{
-    gui::{
-        core_ui::audio_slider::AudioSlider, core_ui::audio_widget::AudioWidgetContainer,
-        events::SynthControlEvent,
-    },
-    messages::ModulatedFilterParams,
-    messages::StateVarTPTFilterParams,
+use effects::filters::{ModulatedFilter, ModulatedFilterStatePacket, MIN_RESONANCE};
+use ravetable_engine::params::ParamId;
+
+use crate::gui::{
+    core_ui::audio_slider::AudioSlider, core_ui::audio_widget::AudioWidgetContainer,
+    events::SynthControlEvent,
 };
 use effects::filters::FilterType;
 
@@ -47,6 +44,12 @@ impl Widget for ModulatedFilterControls {
     fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
         let id = self.osc_id;
         let effect_id = self.effect_id;
+        let set_param = move |param: usize, val: f32| {
+            Event::new(SynthControlEvent::SetParam(
+                ParamId::Effect(id, effect_id, param),
+                val,
+            ))
+        };
 
         let container = AudioWidgetContainer::new("Filter").build(state, entity, |builder| builder);
 
@@ -71,13 +74,10 @@ impl Widget for ModulatedFilterControls {
 
         FILTER_TYPES.iter().for_each(|filter_type| {
             CheckButton::new(false)
-                .on_checked(Event::new(SynthControlEvent::ModulatedFilter(
-                    id,
-                    effect_id,
-                    ModulatedFilterParams::Filter(StateVarTPTFilterParams::FilterType(
-                        *filter_type,
-                    )),
-                )))
+                .on_checked(set_param(
+                    ModulatedFilter::FILTER_TYPE,
+                    filter_type.to_param(),
+                ))
                 .build(state, options, |b| {
                     b.set_text(&format!("{}", filter_type))
                         .set_color(Color::blue())
@@ -91,25 +91,11 @@ impl Widget for ModulatedFilterControls {
 
         AudioSlider::new("Frq", 0., 15_000., self.filter.base_frequency)
             .set_to_round_label(true)
-            .on_change(move |val| {
-                use crate::messages::StateVarTPTFilterParams::*;
-                Event::new(SynthControlEvent::ModulatedFilter(
-                    id,
-                    effect_id,
-                    ModulatedFilterParams::Filter(Frequency(val)),
-                ))
-            })
+            .on_change(move |val| set_param(ModulatedFilter::BASE_FREQUENCY, val))
             .build(state, row, |builder| builder);
 
-        AudioSlider::new("Reso", 0., 1., self.filter.filter.resonance)
-            .on_change(move |val| {
-                use crate::messages::StateVarTPTFilterParams::*;
-                Event::new(SynthControlEvent::ModulatedFilter(
-                    id,
-                    effect_id,
-                    ModulatedFilterParams::Filter(Resonance(val)),
-                ))
-            })
+        AudioSlider::new("Reso", MIN_RESONANCE, 1., self.filter.filter.resonance)
+            .on_change(move |val| set_param(ModulatedFilter::RESONANCE, val))
             .build(state, row, |builder| builder);
 
         let row2 = HBox::new().build(state, container, |builder| {
@@ -136,28 +122,14 @@ impl Widget for ModulatedFilterControls {
                 .set_margin_top(Units::Pixels(-4.))
         });
 
-        ValueKnob::new("Frq", self.filter.lfo.frequency, 0.0, 10.0)
-            .on_change(move |val| {
-                use crate::messages::LfoParams::*;
-                Event::new(SynthControlEvent::ModulatedFilter(
-                    id,
-                    effect_id,
-                    ModulatedFilterParams::Lfo(Frequency(val)),
-                ))
-            })
+        ValueKnob::new("Frq", self.filter.lfo.frequency, 0.0, 20.0)
+            .on_change(move |val| set_param(ModulatedFilter::LFO_FREQUENCY, val))
             .build(state, row3, |builder| {
                 builder.set_width(Units::Pixels(50.0))
             });
 
-        ValueKnob::new("Phase", self.filter.lfo.phase, 0.0, 10.0)
-            .on_change(move |val| {
-                use crate::messages::LfoParams::*;
-                Event::new(SynthControlEvent::ModulatedFilter(
-                    id,
-                    effect_id,
-                    ModulatedFilterParams::Lfo(Phase(val)),
-                ))
-            })
+        ValueKnob::new("Phase", self.filter.lfo.phase, 0.0, 1.0)
+            .on_change(move |val| set_param(ModulatedFilter::LFO_PHASE, val))
             .build(state, row3, |builder| {
                 builder.set_width(Units::Pixels(50.0))
             });
@@ -166,24 +138,19 @@ impl Widget for ModulatedFilterControls {
     }
 
     fn on_event(&mut self, state: &mut State, _entity: Entity, event: &mut Event) {
-        if let Some(SynthControlEvent::ModulatedFilter(osc_id, effect_id, param)) =
-            event.message.downcast::<SynthControlEvent>()
+        if let Some(SynthControlEvent::SetParam(
+            ParamId::Effect(osc_id, effect_id, ModulatedFilter::FILTER_TYPE),
+            value,
+        )) = event.message.downcast::<SynthControlEvent>()
         {
             if self.osc_id == *osc_id && self.effect_id == *effect_id {
-                match param {
-                    ModulatedFilterParams::Filter(StateVarTPTFilterParams::FilterType(
-                        filter_type,
-                    )) => {
-                        let label = format!("{}", filter_type);
-
-                        state.insert_event(
-                            Event::new(DropdownEvent::SetText(label.clone()))
-                                .target(self.dropdown)
-                                .propagate(Propagation::Up),
-                        )
-                    }
-                    _ => {}
-                }
+                let label = format!("{}", FilterType::from_param(*value));
+
+                state.insert_event(
+                    Event::new(DropdownEvent::SetText(label))
+                        .target(self.dropdown)
+                        .propagate(Propagation::Up),
+                )
             }
         }
     }
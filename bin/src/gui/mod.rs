@@ -1,14 +1,16 @@
-use log::info;
+use log::{error, info, warn};
 use tuix::*;
 
-use crate::gui::events::SynthControlEvent;
-use crate::messages::Message;
-use crate::synths::Sample;
+use ravetable_engine::messages::Message;
+use ravetable_engine::mixer::MixerStatePacket;
+use ravetable_engine::preset::{next_preset_path, query_presets, Preset};
+use ravetable_engine::synths::Sample;
+
+use crate::gui::events::{ErrorEvent, PresetEvent, SynthControlEvent};
 use crate::{
     gui::oscillator::Oscillator,
-    keyboard::keyboard_to_midi,
-    mixer::MixerStatePacket,
-    state::{get_midi_keyboard, set_midi_keyboard},
+    gui::preset::PresetControls,
+    keyboard::{keyboard_to_midi, MidiKeyboard},
 };
 
 mod adsr;
@@ -16,16 +18,25 @@ mod core_ui;
 mod events;
 mod filter;
 mod oscillator;
+mod preset;
 
 #[allow(unused)]
 pub struct Controller {
     command_sender: crossbeam_channel::Sender<Message>,
     command_receiver: crossbeam_channel::Receiver<Message>,
 
+    // Kept up to date with every change sent to the mixer, so it can be saved as a preset
     mixer_state_packet: MixerStatePacket,
 
+    // Holds the oscillators, which get rebuilt when a preset is loaded
+    root: Entity,
     oscillators: Vec<Entity>,
-    currently_pressed_keys: Vec<Code>,
+    // Shows the last error that isn't about a single oscillator
+    status_label: Entity,
+    // Remembers which note each key started, so changing octave while holding a key
+    // still releases the right note
+    currently_pressed_keys: Vec<(Code, u8)>,
+    midi_keyboard: MidiKeyboard,
 
     available_samples: Vec<Sample>,
 }
@@ -41,11 +52,94 @@ impl Controller {
             command_sender,
             command_receiver,
             mixer_state_packet,
+            root: Entity::null(),
             oscillators: vec![],
+            status_label: Entity::null(),
             currently_pressed_keys: vec![],
+            midi_keyboard: MidiKeyboard::new(),
             available_samples,
         }
     }
+
+    fn send_message(&mut self, message: Message) {
+        self.mixer_state_packet.apply_message(&message);
+        if let Err(e) = self.command_sender.send(message) {
+            error!("Failed to send message to the mixer: {}", e);
+        }
+    }
+
+    fn show_error(&self, state: &mut State, error: &str) {
+        self.status_label.set_text(state, error);
+    }
+
+    // tuix has no timers, so whatever the mixer sends back is picked up on the next GUI event
+    fn poll_mixer_events(&mut self, state: &mut State) {
+        while let Ok(message) = self.command_receiver.try_recv() {
+            self.mixer_state_packet.apply_message(&message);
+
+            match message {
+                Message::WavetableError(id, sample, error) => {
+                    let oscillator = match self.oscillators.get(id) {
+                        Some(oscillator) => *oscillator,
+                        None => continue,
+                    };
+                    // Back to the wavetable that's still playing
+                    state.insert_event(
+                        Event::new(SynthControlEvent::SampleChange(id, sample))
+                            .target(oscillator)
+                            .propagate(Propagation::Direct),
+                    );
+                    state.insert_event(
+                        Event::new(ErrorEvent::Wavetable(error))
+                            .target(oscillator)
+                            .propagate(Propagation::Direct),
+                    );
+                }
+                Message::Error(error) => self.show_error(state, &error),
+                _ => {}
+            }
+        }
+    }
+
+    fn save_preset(&self) {
+        let path = next_preset_path();
+        match Preset::from_state_packet(&self.mixer_state_packet).save(&path) {
+            Ok(_) => info!("Saved preset to {}", path.display()),
+            Err(e) => error!("Failed to save preset to {}: {}", path.display(), e),
+        }
+    }
+
+    fn load_preset(&mut self, state: &mut State, preset: Preset) {
+        if !preset.matches_layout(&self.mixer_state_packet) {
+            let error =
+                "Preset has different oscillators or effects, restart with --preset to load it";
+            warn!("{}", error);
+            self.show_error(state, error);
+            return;
+        }
+
+        for message in preset.to_messages(&self.available_samples) {
+            self.send_message(message);
+        }
+
+        // Every control shows the new patch
+        for osc in self.oscillators.drain(..) {
+            state.remove(osc);
+        }
+        self.build_oscillators(state);
+    }
+
+    fn build_oscillators(&mut self, state: &mut State) {
+        for (i, oscillator) in self.mixer_state_packet.oscillators.iter().enumerate() {
+            let osc = Oscillator::new(i, oscillator.clone(), self.available_samples.clone()).build(
+                state,
+                self.root,
+                |builder| builder,
+            );
+
+            self.oscillators.push(osc);
+        }
+    }
 }
 
 impl Widget for Controller {
@@ -60,60 +154,53 @@ impl Widget for Controller {
                 .set_flex_direction(FlexDirection::Column)
         });
 
-        for (i, oscillator) in self.mixer_state_packet.oscillators.iter().enumerate() {
-            let osc = Oscillator::new(i, oscillator.clone(), self.available_samples.clone()).build(
-                state,
-                root,
-                |builder| builder,
-            );
+        PresetControls::new(query_presets()).build(state, root, |builder| builder);
 
-            self.oscillators.push(osc);
-        }
+        self.status_label = Label::new("").build(state, root, |builder| {
+            builder
+                .set_color(Color::rgb(230, 80, 80))
+                .set_height(Units::Pixels(20.))
+        });
+
+        self.root = root;
+        self.build_oscillators(state);
         entity
     }
 
-    fn on_event(&mut self, _state: &mut State, _entity: Entity, event: &mut Event) {
+    fn on_event(&mut self, state: &mut State, _entity: Entity, event: &mut Event) {
+        self.poll_mixer_events(state);
+
         if let Some(window_event) = event.message.downcast::<WindowEvent>() {
             match window_event {
                 WindowEvent::KeyDown(code, _) => {
-                    if !self.currently_pressed_keys.contains(code) {
+                    if !self.currently_pressed_keys.iter().any(|(c, _)| c == code) {
                         if let Some(midi_note) = keyboard_to_midi(*code) {
                             info!("first time midi pressed: {:?}", midi_note);
-                            let frq = get_midi_keyboard().get_frequency_from_key(&midi_note);
-                            self.command_sender.send(Message::Note(1.0)).unwrap();
-                            self.command_sender.send(Message::Frequency(frq)).unwrap();
-                            self.currently_pressed_keys.push(*code);
+                            let note = self.midi_keyboard.get_note_from_key(&midi_note);
+                            if let Err(e) = self.command_sender.send(Message::NoteOn(note, 1.0)) {
+                                error!("Failed to send note to the mixer: {}", e);
+                            }
+                            self.currently_pressed_keys.push((*code, note));
                         }
                     }
                     event.consume();
                 }
                 WindowEvent::KeyUp(code, _) => {
                     if *code == Code::KeyZ {
-                        let new_midi_keyboard = get_midi_keyboard().decrease_octave();
-                        set_midi_keyboard(new_midi_keyboard);
+                        self.midi_keyboard = self.midi_keyboard.decrease_octave();
                     } else if *code == Code::KeyX {
-                        let new_midi_keyboard = get_midi_keyboard().increase_octave();
-                        set_midi_keyboard(new_midi_keyboard);
-                    } else if self.currently_pressed_keys.contains(code) {
-                        if let Some(_) = keyboard_to_midi(*code) {
-                            let index = self
-                                .currently_pressed_keys
-                                .iter()
-                                .position(|x| *x == *code)
-                                .unwrap();
-                            self.currently_pressed_keys.remove(index);
-
-                            // If that keyup was the last key pressed, send a message for release
-                            if self.currently_pressed_keys.len() == 0 {
-                                match self.command_sender.send(Message::Note(0.0)) {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        info!(
-                                            "Something terrible happend in gui keyup: {}",
-                                            e.to_string()
-                                        )
-                                    }
-                                }
+                        self.midi_keyboard = self.midi_keyboard.increase_octave();
+                    } else if let Some(index) = self
+                        .currently_pressed_keys
+                        .iter()
+                        .position(|(c, _)| c == code)
+                    {
+                        let (_, note) = self.currently_pressed_keys.remove(index);
+
+                        match self.command_sender.send(Message::NoteOff(note)) {
+                            Ok(_) => {}
+                            Err(e) => {
+                                info!("Something terrible happend in gui keyup: {}", e.to_string())
                             }
                         }
                     }
@@ -123,24 +210,27 @@ impl Widget for Controller {
             }
         }
         // TODO: this SynthControlEvent -> crossbeam messages conversion could probably be implemented as From<T>
-        //       also not happy with the cloning
         if let Some(ev) = event.message.downcast::<SynthControlEvent>() {
-            match ev {
-                SynthControlEvent::OscillatorControl(id, val) => {
-                    self.command_sender
-                        .send(Message::OscChange(*id, val.clone()))
-                        .unwrap();
-                }
-                SynthControlEvent::Envelope(id, val) => {
-                    self.command_sender
-                        .send(Message::EnvelopeChange(*id, val.clone()))
-                        .unwrap();
-                }
-                SynthControlEvent::ModulatedFilter(id, effect_id, val) => {
-                    self.command_sender
-                        .send(Message::ModulatedFilterParams(*id, *effect_id, val.clone()))
-                        .unwrap();
+            let message = match ev {
+                SynthControlEvent::SetParam(id, value) => Message::SetParam(*id, *value),
+                SynthControlEvent::SampleChange(id, sample) => {
+                    Message::SampleChange(*id, sample.clone())
                 }
+            };
+            self.send_message(message);
+        }
+
+        if let Some(ev) = event.message.downcast::<PresetEvent>() {
+            match ev {
+                PresetEvent::Save => self.save_preset(),
+                PresetEvent::Load(path) => match Preset::load(path) {
+                    Ok(preset) => self.load_preset(state, preset),
+                    Err(e) => {
+                        let error = format!("Failed to load preset {}: {}", path.display(), e);
+                        error!("{}", error);
+                        self.show_error(state, &error);
+                    }
+                },
             }
         }
     }
@@ -1,12 +1,12 @@
 use tuix::*;
 
+use ravetable_engine::params::{OscParam, ParamId};
+use ravetable_engine::synths::{OscStatePacket, Sample};
+
 use crate::gui::filter::ModulatedFilterControls;
-use crate::messages::OscParams;
-use crate::messages::OscParams::Gain;
 use crate::{
     gui::adsr::ADSRControls,
-    gui::events::SynthControlEvent,
-    synths::{OscStatePacket, Sample},
+    gui::events::{ErrorEvent, SynthControlEvent},
 };
 use effects::EffectStatePacket;
 
@@ -14,6 +14,9 @@ pub struct Oscillator {
     id: usize,
     osc_state: OscStatePacket,
     available_samples: Vec<Sample>,
+
+    // components
+    controls: Entity,
 }
 
 impl Oscillator {
@@ -22,6 +25,7 @@ impl Oscillator {
             id,
             osc_state,
             available_samples,
+            controls: Entity::null(),
         }
     }
 }
@@ -39,10 +43,13 @@ impl Widget for Oscillator {
         });
 
         // TODO: these available_samples clonings are severely bothering me, borrow instead later
-        OscillatorControls::new(
+        self.controls = OscillatorControls::new(
             id,
             &self.osc_state.name,
             self.osc_state.gain,
+            self.osc_state.position,
+            self.osc_state.coarse,
+            self.osc_state.fine,
             self.available_samples.clone(),
         )
         .build(state, container, |builder| builder);
@@ -56,22 +63,40 @@ impl Widget for Oscillator {
 
         // Skip 1 bc we're not showing the internal low pass filter (yet. TODO)
         for (effect_id, effect) in self.osc_state.effects.iter().skip(1).enumerate() {
-            match effect {
-                EffectStatePacket::ModulatedFilter(e) => {
-                    let effect_id = effect_id + 1; // Plus one since we skipped the first el
-                    ModulatedFilterControls::new(id, effect_id, e.clone()).build(
-                        state,
-                        widget_rack,
-                        |builder| builder,
-                    );
-                }
-                EffectStatePacket::IIRFilter(_) => {}
-                EffectStatePacket::StateVariablePTPFilter(_) => {}
+            // Only modulated filters have controls so far
+            if let EffectStatePacket::ModulatedFilter(e) = effect {
+                let effect_id = effect_id + 1; // Plus one since we skipped the first el
+                ModulatedFilterControls::new(id, effect_id, *e).build(
+                    state,
+                    widget_rack,
+                    |builder| builder,
+                );
             }
         }
 
         entity
     }
+
+    fn on_event(&mut self, state: &mut State, entity: Entity, event: &mut Event) {
+        // Changes sent straight to this oscillator (e.g. from loading a preset) get passed down
+        // to the controls, so they can update what they show
+        if event.target == entity {
+            if let Some(ev) = event.message.downcast::<SynthControlEvent>() {
+                state.insert_event(
+                    Event::new(ev.clone())
+                        .target(self.controls)
+                        .propagate(Propagation::Direct),
+                );
+            }
+            if let Some(ev) = event.message.downcast::<ErrorEvent>() {
+                state.insert_event(
+                    Event::new(ev.clone())
+                        .target(self.controls)
+                        .propagate(Propagation::Direct),
+                );
+            }
+        }
+    }
 }
 
 pub struct OscillatorControls {
@@ -81,11 +106,17 @@ pub struct OscillatorControls {
     sample_label: String,
     available_samples: Vec<Sample>,
     gain: f32,
+    position: f32,
+    coarse: f32,
+    fine: f32,
 
     // components
     pub dropdown: Entity,
     pub gain_knob: Entity,
-    pub frequency_knob: Entity,
+    pub position_knob: Entity,
+    pub coarse_knob: Entity,
+    pub fine_knob: Entity,
+    pub error_label: Entity,
     pub active_toggle: Entity,
 }
 
@@ -94,6 +125,9 @@ impl OscillatorControls {
         id: usize,
         label: T,
         gain: f32,
+        position: f32,
+        coarse: f32,
+        fine: f32,
         available_samples: Vec<Sample>,
     ) -> Self {
         OscillatorControls {
@@ -101,9 +135,15 @@ impl OscillatorControls {
             sample_label: label.into(),
             available_samples,
             gain,
+            position,
+            coarse,
+            fine,
             dropdown: Entity::null(),
             gain_knob: Entity::null(),
-            frequency_knob: Entity::null(),
+            position_knob: Entity::null(),
+            coarse_knob: Entity::null(),
+            fine_knob: Entity::null(),
+            error_label: Entity::null(),
             active_toggle: Entity::null(),
         }
     }
@@ -129,7 +169,7 @@ impl Widget for OscillatorControls {
                 .set_width(Units::Pixels(200.))
                 .set_margin_bottom(Units::Pixels(5.0))
         });
-        let _row1 = HBox::new().build(state, container, |builder| {
+        let row1 = HBox::new().build(state, container, |builder| {
             builder
                 .set_justify_content(JustifyContent::SpaceEvenly)
                 .set_height(Units::Pixels(100.))
@@ -154,9 +194,9 @@ impl Widget for OscillatorControls {
             .enumerate()
             .for_each(|(idx, sample)| {
                 CheckButton::new(false)
-                    .on_checked(Event::new(SynthControlEvent::OscillatorControl(
+                    .on_checked(Event::new(SynthControlEvent::SampleChange(
                         id,
-                        OscParams::SampleChange(self.available_samples[idx].clone()),
+                        self.available_samples[idx].clone(),
                     )))
                     .build(state, options, |b| {
                         b.set_text(&sample.name)
@@ -169,44 +209,68 @@ impl Widget for OscillatorControls {
 
         self.dropdown = dropdown;
 
-        self.gain_knob = ValueKnob::new("Gain", self.gain, 0.0, 1.0)
-            .on_change(move |val| Event::new(SynthControlEvent::OscillatorControl(id, Gain(val))))
+        self.error_label = Label::new("").build(state, row1, |builder| {
+            builder
+                .set_color(Color::rgb(230, 80, 80))
+                .set_width(Units::Pixels(190.))
+        });
+
+        let knob = |param: OscParam, value: f32| {
+            let info = param.info();
+            ValueKnob::new(info.name, value, info.min, info.max)
+        };
+        let set_param = move |param: OscParam, val: f32| {
+            Event::new(SynthControlEvent::SetParam(ParamId::Osc(id, param), val))
+        };
+
+        self.gain_knob = knob(OscParam::Gain, self.gain)
+            .on_change(move |val| set_param(OscParam::Gain, val))
+            .build(state, row2, |builder| {
+                builder.set_width(Units::Pixels(50.0))
+            });
+
+        self.position_knob = knob(OscParam::Position, self.position)
+            .on_change(move |val| set_param(OscParam::Position, val))
             .build(state, row2, |builder| {
                 builder.set_width(Units::Pixels(50.0))
             });
 
-        // TODO: This was here pre-keyboard midi input to test the synth. Just a single gain knob
-        //       is lonely, so I'm finding something to replace it with.
-        //       The popular choice so far is a Sausage Fattener knob that does nothing
-        // self.frequency_knob = ValueKnob::new("Frequency", 440.0, 0.0, 6000.0)
-        //     // .on_change(move |val| {
-        //     // })
-        //     .build(state, row2, |builder| {
-        //         builder.set_width(Units::Pixels(50.0))
-        //     });
+        // Coarse tuning snaps to whole semitones
+        self.coarse_knob = knob(OscParam::Coarse, self.coarse)
+            .on_change(move |val| set_param(OscParam::Coarse, val.round()))
+            .build(state, row2, |builder| {
+                builder.set_width(Units::Pixels(50.0))
+            });
+
+        self.fine_knob = knob(OscParam::Fine, self.fine)
+            .on_change(move |val| set_param(OscParam::Fine, val))
+            .build(state, row2, |builder| {
+                builder.set_width(Units::Pixels(50.0))
+            });
 
         entity
     }
 
     fn on_event(&mut self, state: &mut State, _entity: Entity, event: &mut Event) {
-        if let Some(SynthControlEvent::OscillatorControl(idx, param)) =
+        if let Some(SynthControlEvent::SampleChange(idx, sample)) =
             event.message.downcast::<SynthControlEvent>()
         {
             if self.id == *idx {
-                match param {
-                    OscParams::SampleChange(sample) => {
-                        let label = &sample.name;
-
-                        self.sample_label = label.to_string();
-                        state.insert_event(
-                            Event::new(DropdownEvent::SetText(label.clone()))
-                                .target(self.dropdown)
-                                .propagate(Propagation::Up),
-                        );
-                    }
-                    _ => {}
-                }
+                let label = &sample.name;
+
+                self.sample_label = label.to_string();
+                state.insert_event(
+                    Event::new(DropdownEvent::SetText(label.clone()))
+                        .target(self.dropdown)
+                        .propagate(Propagation::Up),
+                );
+                // Whatever went wrong last time is about a different wavetable
+                self.error_label.set_text(state, "");
             }
         }
+
+        if let Some(ErrorEvent::Wavetable(error)) = event.message.downcast::<ErrorEvent>() {
+            self.error_label.set_text(state, error);
+        }
     }
 }
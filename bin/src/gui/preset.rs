@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use tuix::*;
+
+use crate::gui::events::PresetEvent;
+
+pub struct PresetControls {
+    available_presets: Vec<PathBuf>,
+
+    // components
+    dropdown: Entity,
+}
+
+impl PresetControls {
+    pub fn new(available_presets: Vec<PathBuf>) -> Self {
+        PresetControls {
+            available_presets,
+            dropdown: Entity::null(),
+        }
+    }
+}
+
+impl Widget for PresetControls {
+    type Ret = Entity;
+
+    fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
+        let row = HBox::new().build(state, entity, |builder| {
+            builder
+                .set_justify_content(JustifyContent::FlexStart)
+                .set_align_items(AlignItems::Center)
+                .set_height(Units::Pixels(40.))
+        });
+
+        let (_, _, dropdown) = Dropdown::new("Load preset").build(state, row, |b| {
+            b.set_height(Units::Pixels(30.0))
+                .set_width(Units::Pixels(175.))
+                .set_margin_right(Units::Pixels(8.))
+        });
+        let options = List::new().build(state, dropdown, |b| b);
+
+        self.available_presets.iter().for_each(|preset| {
+            let name = preset
+                .file_stem()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            CheckButton::new(false)
+                .on_checked(Event::new(PresetEvent::Load(preset.clone())))
+                .build(state, options, |b| {
+                    b.set_text(&name)
+                        .set_color(Color::blue())
+                        .set_height(Pixels(30.0))
+                        .set_width(Units::Pixels(175.))
+                        .set_margin_left(Pixels(5.0))
+                });
+        });
+
+        self.dropdown = dropdown;
+
+        Button::with_label("Save preset")
+            .on_release(Event::new(PresetEvent::Save))
+            .build(state, row, |b| {
+                b.set_height(Units::Pixels(30.0))
+                    .set_width(Units::Pixels(100.))
+            });
+
+        entity
+    }
+
+    fn on_event(&mut self, state: &mut State, _entity: Entity, event: &mut Event) {
+        if let Some(PresetEvent::Load(path)) = event.message.downcast::<PresetEvent>() {
+            let label = path
+                .file_stem()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            state.insert_event(
+                Event::new(DropdownEvent::SetText(label))
+                    .target(self.dropdown)
+                    .propagate(Propagation::Up),
+            );
+        }
+    }
+}
@@ -40,12 +40,11 @@ impl MidiKeyboard {
         self
     }
 
-    pub fn get_frequency_from_key(&self, key_pressed: &MidiNote) -> f32 {
+    pub fn get_note_from_key(&self, key_pressed: &MidiNote) -> u8 {
         let mut keypress = *key_pressed as i16;
         keypress += self.octave * 12;
 
-        let freq = 440. * 2_f32.powf((keypress - 69) as f32 / 12.);
-        freq
+        keypress.clamp(0, 127) as u8
     }
 }
 
@@ -2,30 +2,37 @@
 //!
 //! Katherine Philip (For CS 410P/510 Computers, Sound and Music (Spring 2021))
 
-use std::path::Path;
 use std::thread;
 
 use cpal::traits::{DeviceTrait, HostTrait};
-use log::info;
+use log::{error, info};
 use tuix::*;
 
-use effects::filters::{Filter, FilterType, ModulatedFilter, StateVariableTPTFilter};
-use effects::lfo::{Lfo, LfoType};
-use effects::Effect;
+use effects::smoothing::DEFAULT_SMOOTHING_TIME;
+use ravetable_engine::loader::start_wavetable_loader;
+use ravetable_engine::messages;
+use ravetable_engine::mixer::MixerStatePacket;
+use ravetable_engine::patch::{build_mixer, DEFAULT_VOICE_COUNT};
+use ravetable_engine::preset::Preset;
+use ravetable_engine::synths::{query_samples, Sample, DEFAULT_CROSSFADE_TIME};
+use ravetable_engine::tuning::DEFAULT_MASTER_TUNE;
+use ravetable_engine::Synth;
 
+use crate::error::RavetableError;
 use crate::gui::Controller;
-use crate::mixer::{Mixer, MixerStatePacket};
+use crate::midi::start_midi_input;
+use crate::midi_file::load_midi_file;
 use crate::playback::run;
-use crate::state::{get_sample_rate, set_sample_rate};
-use crate::synths::{Oscillator, Sample, Wavetable};
+use crate::render::{render, RenderOpt};
 
+mod alloc_check;
+mod error;
 mod gui;
 mod keyboard;
-mod messages;
-mod mixer;
+mod midi;
+mod midi_file;
 mod playback;
-mod state;
-mod synths;
+mod render;
 
 #[derive(Debug)]
 struct Opt {
@@ -36,87 +43,222 @@ struct Opt {
     jack: bool,
 
     device: String,
+    midi_port: Option<String>,
+    downmix: bool,
+    midi_file: Option<String>,
+    preset: Option<String>,
+    save_preset: Option<String>,
+    frame_size: Option<usize>,
+    voices: usize,
+    master_tune: f32,
+    // Seconds
+    crossfade_time: f32,
+    smoothing_time: f32,
+    render: Option<RenderOpt>,
 }
 
-pub type CrossbeamReceiver = crossbeam_channel::Receiver<messages::Message>;
-pub type CrossbeamSender = crossbeam_channel::Sender<messages::Message>;
-
-fn query_samples(samples_path: &str) -> Vec<Sample> {
-    let base_path = Path::new(".");
-    let sample_path = base_path.join(Path::new(samples_path));
+impl Opt {
+    fn from_args() -> Result<Self, anyhow::Error> {
+        let app = clap::App::new("Ravetable")
+            .arg_from_usage("[DEVICE] 'The audio device to use'")
+            .arg_from_usage(
+                "-m, --midi-port [PORT] 'MIDI port to listen to, either as client:port or by name'",
+            )
+            .arg_from_usage("-f, --midi-file [FILE] 'Standard MIDI file to play on startup'")
+            .arg_from_usage("-p, --preset [FILE] 'Preset to load on startup'")
+            .arg_from_usage(
+                "--save-preset [FILE] 'Writes the startup patch (default or --preset) to a preset file and exits'",
+            )
+            .arg_from_usage(
+                "--frame-size [SAMPLES] 'Frame size of wavetables without a Serum clm chunk, otherwise the whole file is a single frame'",
+            )
+            .arg_from_usage("--downmix 'Plays stereo wavetables in mono'")
+            .arg_from_usage("--voices [COUNT] 'Notes that can play at once, defaults to 8'")
+            .arg_from_usage("--tune [HZ] 'Frequency of A4, defaults to 440'")
+            .arg_from_usage(
+                "--crossfade [MS] 'Time it takes to fade into a newly picked wavetable, defaults to 50'",
+            )
+            .arg_from_usage(
+                "--smoothing [MS] 'Time it takes gain and filter changes to ramp to their new values, defaults to 20'",
+            )
+            .subcommand(RenderOpt::subcommand());
+        #[cfg(all(
+            any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+            feature = "jack"
+        ))]
+        let app = app.arg_from_usage("-j, --jack 'Use the JACK host'");
+        let matches = app.get_matches();
+        let device = matches.value_of("DEVICE").unwrap_or("default").to_string();
+        let midi_port = matches.value_of("midi-port").map(String::from);
+        let downmix = matches.is_present("downmix");
+        let midi_file = matches.value_of("midi-file").map(String::from);
+        let preset = matches.value_of("preset").map(String::from);
+        let save_preset = matches.value_of("save-preset").map(String::from);
+        let frame_size = match matches.value_of("frame-size") {
+            Some(size) => Some(size.parse()?),
+            None => None,
+        };
+        let voices = match matches.value_of("voices") {
+            Some(count) => count.parse()?,
+            None => DEFAULT_VOICE_COUNT,
+        };
+        let master_tune = match matches.value_of("tune") {
+            Some(tune) => tune.parse()?,
+            None => DEFAULT_MASTER_TUNE,
+        };
+        let crossfade_time = match matches.value_of("crossfade") {
+            Some(ms) => ms.parse::<f32>()? / 1000.,
+            None => DEFAULT_CROSSFADE_TIME,
+        };
+        let smoothing_time = match matches.value_of("smoothing") {
+            Some(ms) => ms.parse::<f32>()? / 1000.,
+            None => DEFAULT_SMOOTHING_TIME,
+        };
+        let render = match matches.subcommand_matches("render") {
+            Some(render_matches) => Some(RenderOpt::from_matches(render_matches)?),
+            None => None,
+        };
 
-    let dir = std::fs::read_dir(sample_path).unwrap();
+        #[cfg(all(
+            any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+            feature = "jack"
+        ))]
+        return Ok(Opt {
+            jack: matches.is_present("jack"),
+            device,
+            midi_port,
+            downmix,
+            midi_file,
+            preset,
+            save_preset,
+            frame_size,
+            voices,
+            master_tune,
+            crossfade_time,
+            smoothing_time,
+            render,
+        });
 
-    dir.map(|d| {
-        let d = d.unwrap();
-        Sample {
-            name: d.file_name().to_os_string().into_string().unwrap(),
-            path: d.path().into_os_string().into_string().unwrap(),
-        }
-    })
-    .collect()
+        #[cfg(any(
+            not(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd")),
+            not(feature = "jack")
+        ))]
+        Ok(Opt {
+            device,
+            midi_port,
+            downmix,
+            midi_file,
+            preset,
+            save_preset,
+            frame_size,
+            voices,
+            master_tune,
+            crossfade_time,
+            smoothing_time,
+            render,
+        })
+    }
 }
 
+pub type CrossbeamReceiver = crossbeam_channel::Receiver<messages::Message>;
+pub type CrossbeamSender = crossbeam_channel::Sender<messages::Message>;
+
+const SAMPLES_PATH: &str = "wavetable/";
+
 fn main() -> Result<(), anyhow::Error> {
     let (gui_tx, audio_rx) = crossbeam_channel::bounded(1024);
-    let (_audio_tx, gui_rx) = crossbeam_channel::bounded(32);
+    let (audio_tx, gui_rx) = crossbeam_channel::bounded(32);
 
     init_logger();
+    let opt = Opt::from_args()?;
 
-    let samples_path = "wavetable/";
-    let samples = query_samples(samples_path); // and called here
+    let samples = query_samples(SAMPLES_PATH, opt.frame_size, opt.downmix)?;
 
+    if let Some(path) = &opt.save_preset {
+        let mixer = build_mixer(&samples, 48000, opt.voices, opt.preset.as_deref())?;
+        Preset::from_state_packet(&mixer.get_state_packet()).save(path)?;
+        info!("Saved preset to {}", path);
+        return Ok(());
+    }
+
+    if let Some(render_opt) = &opt.render {
+        let preset = render_opt.preset.as_deref().or(opt.preset.as_deref());
+        let mixer = build_mixer(&samples, render_opt.sample_rate, opt.voices, preset)?
+            .with_master_tune(opt.master_tune)
+            .with_parameter_smoothing(opt.smoothing_time);
+        return render(render_opt, Synth::new(mixer));
+    }
+
+    #[cfg(all(
+        any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+        feature = "jack"
+    ))]
+    let host = if opt.jack {
+        cpal::host_from_id(
+            cpal::available_hosts()
+                .into_iter()
+                .find(|id| *id == cpal::HostId::Jack)
+                .ok_or(RavetableError::JackUnavailable)?,
+        )
+        .map_err(RavetableError::from)?
+    } else {
+        cpal::default_host()
+    };
+
+    #[cfg(any(
+        not(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd")),
+        not(feature = "jack")
+    ))]
     let host = cpal::default_host();
 
-    let device = host.default_output_device().expect("Device failed");
+    let device = if opt.device == "default" {
+        host.default_output_device()
+            .ok_or(RavetableError::NoDefaultDevice)?
+    } else {
+        host.output_devices()
+            .map_err(RavetableError::from)?
+            .find(|d| d.name().map(|n| n == opt.device).unwrap_or(false))
+            .ok_or_else(|| RavetableError::DeviceNotFound(opt.device.clone()))?
+    };
     info!(
         "Output device: {}",
-        device.name().expect("No output device found")
+        device.name().unwrap_or_else(|_| opt.device.clone())
     );
 
-    let config = device.default_output_config().unwrap();
+    let config = device
+        .default_output_config()
+        .map_err(RavetableError::from)?;
     info!("Default output config: {:?}", config);
     let sample_rate = config.sample_rate();
 
-    set_sample_rate(sample_rate);
-
-    let wavetable = Wavetable::create_wavetable(samples[0].clone(), config.sample_rate().0);
-    let mut osc = Oscillator::new(0.5, 1440., wavetable);
-    osc.add_effect(Effect::ModulatedFilter(ModulatedFilter::new(
-        // TODO: frequency is all weird now since it gets chunked
-        //       it's only calcing the LFO for the _sample time at chunk request_
-        //       need to advance it into the future like we did for adsr too
-        Lfo::new(LfoType::Sine, 0.5, 1.),
-        Filter::StateVariableTPTFilter(StateVariableTPTFilter::new(
-            get_sample_rate(),
-            2000.,
-            FilterType::LowPass,
-        )),
-        2000.,
-    )));
-
-    let wavetable2 = Wavetable::create_wavetable(samples[0].clone(), config.sample_rate().0);
-    let mut osc2 = Oscillator::new(0.2, 440., wavetable2);
-    osc2.add_effect(Effect::ModulatedFilter(ModulatedFilter::new(
-        Lfo::new(LfoType::Sine, 0., 1.),
-        Filter::StateVariableTPTFilter(StateVariableTPTFilter::new(
-            get_sample_rate(),
-            2000.,
-            FilterType::LowPass,
-        )),
-        2000.,
-    )));
-
-    let mixer = Mixer::new(vec![osc, osc2]);
-    let mixer_state_packet = mixer.get_state_packet().clone();
+    let mixer = build_mixer(&samples, sample_rate.0, opt.voices, opt.preset.as_deref())?
+        .with_master_tune(opt.master_tune)
+        .with_wavetable_crossfade(opt.crossfade_time)
+        .with_parameter_smoothing(opt.smoothing_time);
+    let wavetable_loader = start_wavetable_loader(mixer.get_wavetables(), audio_tx.clone());
+    let mut synth = Synth::new(mixer.with_wavetable_loader(wavetable_loader));
+    if let Some(midi_file) = &opt.midi_file {
+        synth.play_sequence(load_midi_file(midi_file, sample_rate.0)?);
+    }
+    let mixer_state_packet = synth.get_state_packet();
+
+    // Keeps running in the background for as long as the program does
+    if let Err(e) = start_midi_input(opt.midi_port.as_deref(), gui_tx.clone()) {
+        error!("Failed to start MIDI input: {}", e);
+    }
 
     // Audio backend must be started first, as GUI runs on main thread because of OSX
     thread::spawn(move || {
-        let _ = match config.sample_format() {
-            cpal::SampleFormat::F32 => run::<f32>(&device, &config.into(), mixer, audio_rx.clone()),
-            cpal::SampleFormat::I16 => run::<i16>(&device, &config.into(), mixer, audio_rx.clone()),
-            cpal::SampleFormat::U16 => run::<u16>(&device, &config.into(), mixer, audio_rx.clone()),
+        let result = match config.sample_format() {
+            cpal::SampleFormat::F32 => run::<f32>(&device, &config, synth, audio_rx.clone()),
+            cpal::SampleFormat::I16 => run::<i16>(&device, &config, synth, audio_rx.clone()),
+            cpal::SampleFormat::U16 => run::<u16>(&device, &config, synth, audio_rx.clone()),
         };
+
+        if let Err(e) = result {
+            error!("Audio playback failed: {}", e);
+            let _ = audio_tx.send(messages::Message::Error(e.to_string()));
+        }
     });
 
     start_gui(gui_tx.clone(), gui_rx.clone(), mixer_state_packet, samples);
@@ -0,0 +1,166 @@
+//! MIDI input through the ALSA sequencer
+//!
+//! Ravetable registers itself as a sequencer client with a single writable port, so anything
+//! (hardware, a virtual keyboard, `aconnect`, `snd-seq-dummy`...) can be connected to it.
+//! Incoming events are translated into `Message`s and sent down the same crossbeam channel
+//! the GUI uses.
+
+use std::thread;
+
+use ravetable_engine::messages::{Message, MOD_WHEEL_CC};
+
+use crate::CrossbeamSender;
+
+pub fn note_on(note: u8, velocity: u8) -> Message {
+    // Running status keyboards send note-offs as note-ons with zero velocity
+    if velocity == 0 {
+        Message::NoteOff(note)
+    } else {
+        Message::NoteOn(note, velocity as f32 / 127.)
+    }
+}
+
+pub fn note_off(note: u8) -> Message {
+    Message::NoteOff(note)
+}
+
+/// value is the signed 14 bit bend, -8192 to 8191
+pub fn pitch_bend(value: i32) -> Message {
+    let bend = if value < 0 {
+        value as f32 / 8192.
+    } else {
+        value as f32 / 8191.
+    };
+    Message::PitchBend(bend.clamp(-1., 1.))
+}
+
+pub fn control_change(controller: u8, value: u8) -> Message {
+    let value = value as f32 / 127.;
+    match controller {
+        MOD_WHEEL_CC => Message::ModWheel(value),
+        _ => Message::ControlChange(controller, value),
+    }
+}
+
+/// Channel pressure, value is 0 to 127
+pub fn aftertouch(value: u8) -> Message {
+    Message::Aftertouch(value as f32 / 127.)
+}
+
+/// Opens the sequencer client and starts forwarding events on a background thread.
+///
+/// port can be either a "client:port" address such as "20:0", or (part of) a port name.
+/// When it's None, nothing is connected and the port has to be connected from the outside.
+#[cfg(target_os = "linux")]
+pub fn start_midi_input(
+    port: Option<&str>,
+    sender: CrossbeamSender,
+) -> Result<thread::JoinHandle<()>, anyhow::Error> {
+    use alsa::seq::{Addr, PortCap, PortSubscribe, PortType, Seq};
+    use alsa::Direction;
+    use log::{error, info};
+    use std::ffi::CString;
+
+    let seq = Seq::open(None, Some(Direction::Capture), false)?;
+    seq.set_client_name(&CString::new("Ravetable")?)?;
+
+    let input_port = seq.create_simple_port(
+        &CString::new("Ravetable input")?,
+        PortCap::WRITE | PortCap::SUBS_WRITE,
+        PortType::MIDI_GENERIC | PortType::APPLICATION,
+    )?;
+    let own_addr = Addr {
+        client: seq.client_id()?,
+        port: input_port,
+    };
+    info!(
+        "MIDI input listening on {}:{}",
+        own_addr.client, own_addr.port
+    );
+
+    if let Some(port) = port {
+        let source = match port.parse::<Addr>() {
+            Ok(addr) => addr,
+            Err(_) => find_port_by_name(&seq, port)?,
+        };
+
+        let subscription = PortSubscribe::empty()?;
+        subscription.set_sender(source);
+        subscription.set_dest(own_addr);
+        seq.subscribe_port(&subscription)?;
+        info!("Connected MIDI input to {}:{}", source.client, source.port);
+    }
+
+    let handle = thread::spawn(move || {
+        let mut input = seq.input();
+        loop {
+            let event = match input.event_input() {
+                Ok(event) => event,
+                Err(e) => {
+                    error!("Failed to read MIDI event: {}", e);
+                    continue;
+                }
+            };
+
+            if let Some(message) = translate_event(&event) {
+                if sender.send(message).is_err() {
+                    // Audio side is gone, nothing left to play
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+#[cfg(target_os = "linux")]
+fn find_port_by_name(seq: &alsa::Seq, name: &str) -> Result<alsa::seq::Addr, anyhow::Error> {
+    use alsa::seq::{ClientIter, PortCap, PortIter};
+
+    for client in ClientIter::new(seq) {
+        for port in PortIter::new(seq, client.get_client()) {
+            let readable = port
+                .get_capability()
+                .contains(PortCap::READ | PortCap::SUBS_READ);
+            let matches = port.get_name().map(|n| n.contains(name)).unwrap_or(false)
+                || client.get_name().map(|n| n.contains(name)).unwrap_or(false);
+
+            if readable && matches {
+                return Ok(port.addr());
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!("No MIDI port matching \"{}\" found", name))
+}
+
+#[cfg(target_os = "linux")]
+fn translate_event(event: &alsa::seq::Event) -> Option<Message> {
+    use alsa::seq::{EvCtrl, EvNote, EventType};
+
+    match event.get_type() {
+        EventType::Noteon => event
+            .get_data::<EvNote>()
+            .map(|e| note_on(e.note, e.velocity)),
+        EventType::Noteoff => event.get_data::<EvNote>().map(|e| note_off(e.note)),
+        EventType::Pitchbend => event.get_data::<EvCtrl>().map(|e| pitch_bend(e.value)),
+        EventType::Controller => event
+            .get_data::<EvCtrl>()
+            .map(|e| control_change(e.param as u8, e.value.clamp(0, 127) as u8)),
+        EventType::Chanpress => event
+            .get_data::<EvCtrl>()
+            .map(|e| aftertouch(e.value.clamp(0, 127) as u8)),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start_midi_input(
+    _port: Option<&str>,
+    _sender: CrossbeamSender,
+) -> Result<thread::JoinHandle<()>, anyhow::Error> {
+    Err(anyhow::anyhow!(
+        "MIDI input is currently only supported through ALSA on Linux"
+    ))
+}
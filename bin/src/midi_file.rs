@@ -0,0 +1,109 @@
+//! Standard MIDI File loading
+//!
+//! All tracks are merged into a single list of messages, timed in frames at the requested
+//! sample rate, ready to be handed to `Mixer::play_sequence`.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use midly::{Format, MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use ravetable_engine::messages::Message;
+
+use crate::midi::{aftertouch, control_change, note_off, note_on, pitch_bend};
+
+// 120 bpm, used until the file sets a tempo
+const DEFAULT_TEMPO: u32 = 500_000;
+
+enum TimedEvent {
+    Tempo(u32),
+    Message(Message),
+}
+
+pub fn load_midi_file<P: AsRef<Path>>(
+    path: P,
+    sample_rate: u32,
+) -> Result<Vec<(u64, Message)>, anyhow::Error> {
+    let data = std::fs::read(path.as_ref())?;
+    let smf = Smf::parse(&data)
+        .map_err(|e| anyhow!("Failed to parse {}: {}", path.as_ref().display(), e))?;
+
+    // Absolute tick of every event. Sequential files play their tracks one after another,
+    // the others play all tracks at once
+    let mut events: Vec<(u64, TimedEvent)> = Vec::new();
+    let mut track_offset = 0;
+    for track in &smf.tracks {
+        let mut tick = track_offset;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            if let Some(timed_event) = convert_event(&event.kind) {
+                events.push((tick, timed_event));
+            }
+        }
+
+        if smf.header.format == Format::Sequential {
+            track_offset = tick;
+        }
+    }
+
+    // Stable sort, so events on the same tick keep their order within the track
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut tempo = DEFAULT_TEMPO;
+    let mut last_tick = 0;
+    let mut seconds = 0.;
+    let mut sequence = Vec::with_capacity(events.len());
+
+    for (tick, event) in events {
+        seconds += (tick - last_tick) as f64 * seconds_per_tick(smf.header.timing, tempo);
+        last_tick = tick;
+
+        match event {
+            TimedEvent::Tempo(new_tempo) => {
+                tempo = new_tempo;
+
+                // Tempo synced LFOs follow the file too
+                let frame = (seconds * sample_rate as f64).round() as u64;
+                let bpm = 60_000_000. / tempo as f32;
+                sequence.push((frame, Message::Tempo(bpm)));
+            }
+            TimedEvent::Message(message) => {
+                let frame = (seconds * sample_rate as f64).round() as u64;
+                sequence.push((frame, message));
+            }
+        }
+    }
+
+    Ok(sequence)
+}
+
+fn seconds_per_tick(timing: Timing, tempo: u32) -> f64 {
+    match timing {
+        Timing::Metrical(ticks_per_beat) => {
+            tempo as f64 / 1_000_000. / ticks_per_beat.as_int() as f64
+        }
+        // Timecode timing ignores tempo changes completely
+        Timing::Timecode(fps, ticks_per_frame) => {
+            1. / (fps.as_f32() as f64 * ticks_per_frame as f64)
+        }
+    }
+}
+
+fn convert_event(kind: &TrackEventKind) -> Option<TimedEvent> {
+    match kind {
+        TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => Some(TimedEvent::Tempo(tempo.as_int())),
+        TrackEventKind::Midi { message, .. } => {
+            let message = match *message {
+                MidiMessage::NoteOn { key, vel } => note_on(key.as_int(), vel.as_int()),
+                MidiMessage::NoteOff { key, .. } => note_off(key.as_int()),
+                MidiMessage::PitchBend { bend } => pitch_bend(bend.as_int() as i32),
+                MidiMessage::Controller { controller, value } => {
+                    control_change(controller.as_int(), value.as_int())
+                }
+                MidiMessage::ChannelAftertouch { vel } => aftertouch(vel.as_int()),
+                _ => return None,
+            };
+            Some(TimedEvent::Message(message))
+        }
+        _ => None,
+    }
+}
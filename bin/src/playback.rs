@@ -1,42 +1,58 @@
 use cpal::traits::{DeviceTrait, StreamTrait};
 use log::info;
 
-use crate::{messages::Message, mixer::Mixer};
+use ravetable_engine::messages::Message;
+use ravetable_engine::Synth;
+
+use crate::{alloc_check::assert_no_alloc, error::RavetableError};
 
 pub fn run<T>(
     device: &cpal::Device,
-    config: &cpal::StreamConfig,
-    mut mixer: Mixer,
+    supported_config: &cpal::SupportedStreamConfig,
+    mut synth: Synth,
     command_receiver: crossbeam_channel::Receiver<Message>,
-) -> Result<(), anyhow::Error>
+) -> Result<(), RavetableError>
 where
     T: cpal::Sample,
 {
+    let mut config = supported_config.config();
     let output_channels = config.channels as usize;
-    let input_channels = mixer.channels;
-
-    /////// INPUT WAV VERSION
-    // let mut next_value = move || {
-    //     samples.next().unwrap()
-    // };
+    let input_channels = synth.get_channels();
 
-    /////// SINE OSC VERSION
-    // let stream_config = StreamConfig {
-    //     channels: config.channels,
-    //     sample_rate: config.sample_rate,
-    //     buffer_size: BufferSize::Fixed(mixer.chunk_size),
-    // };
+    // Asks for callbacks of one block each, as close to the synth's as the device allows
+    if let cpal::SupportedBufferSize::Range { min, max } = supported_config.buffer_size() {
+        let frames = (synth.get_block_size() as u32 / input_channels as u32).clamp(*min, *max);
+        config.buffer_size = cpal::BufferSize::Fixed(frames);
+        synth.set_block_size(frames as usize * input_channels as usize);
+    }
+    info!("Buffer size: {:?}", config.buffer_size);
 
-    let mut next_value = move || mixer.get_next_sample_chunked(&command_receiver);
+    // Handed to the synth one block at a time, allocated up front so the callback never has to.
+    // Devices that don't take a fixed size get their callbacks split into blocks
+    let mut buffer = vec![0.; synth.get_block_size()];
+    let frames_per_block = buffer.len() / input_channels as usize;
 
     let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
     info!("Channels for output: {}", output_channels);
 
     let stream = device.build_output_stream(
-        config,
+        &config,
         move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            write_data(data, output_channels, input_channels, &mut next_value)
+            for output in data.chunks_mut(frames_per_block * output_channels) {
+                let frames = output.len() / output_channels;
+                let block = &mut buffer[..frames * input_channels as usize];
+
+                // Whatever the messages own gets freed on the wavetable loader's thread
+                assert_no_alloc(|| {
+                    for message in command_receiver.try_iter() {
+                        synth.handle_message(message);
+                    }
+                    synth.process(block);
+                });
+
+                write_data(output, output_channels, block, input_channels);
+            }
         },
         err_fn,
     )?;
@@ -47,28 +63,18 @@ where
     Ok(())
 }
 
-fn write_data<T>(
-    output: &mut [T],
-    output_channels: usize,
-    input_channels: u16,
-    next_sample: &mut dyn FnMut() -> f32,
-) where
+fn write_data<T>(output: &mut [T], output_channels: usize, input: &[f32], input_channels: u16)
+where
     T: cpal::Sample,
 {
-    for frame in output.chunks_mut(output_channels) {
-        match input_channels {
-            1 => {
-                let value: T = cpal::Sample::from::<f32>(&next_sample());
-                for sample in frame.iter_mut() {
-                    *sample = value;
-                }
-            }
-            2 => {
-                for sample in frame.iter_mut() {
-                    *sample = cpal::Sample::from::<f32>(&next_sample());
-                }
-            }
-            _ => panic!("Unsupported channels found in input audio"),
+    let input_channels = input_channels as usize;
+    for (frame, input_frame) in output
+        .chunks_mut(output_channels)
+        .zip(input.chunks(input_channels))
+    {
+        // Channels the synth doesn't have wrap around, so mono plays on every output channel
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            *sample = cpal::Sample::from::<f32>(&input_frame[channel % input_channels]);
         }
     }
 }
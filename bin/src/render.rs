@@ -0,0 +1,224 @@
+//! Offline rendering into a .wav file, without opening a window or an audio device
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::info;
+use ravetable_engine::messages::Message;
+use ravetable_engine::Synth;
+
+use crate::midi_file::load_midi_file;
+
+// Sample rates the synth can render at, the filters need some room below Nyquist
+const MIN_SAMPLE_RATE: u32 = 8000;
+const MAX_SAMPLE_RATE: u32 = 384_000;
+
+/// A single note to render, times are in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoteEvent {
+    pub note: u8,
+    pub start: f32,
+    pub length: f32,
+    pub velocity: f32,
+}
+
+impl FromStr for NoteEvent {
+    type Err = anyhow::Error;
+
+    // note:start:length[:velocity], e.g. 60:0:1.5 or 64:0.5:1:0.8
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        if parts.len() < 3 || parts.len() > 4 {
+            return Err(anyhow!(
+                "Invalid note \"{}\", expected note:start:length[:velocity]",
+                s
+            ));
+        }
+
+        let note = parts[0].parse::<u8>()?;
+        if note > 127 {
+            return Err(anyhow!("Note {} is out of the MIDI range", note));
+        }
+
+        Ok(NoteEvent {
+            note,
+            start: parts[1].parse()?,
+            length: parts[2].parse()?,
+            velocity: match parts.get(3) {
+                Some(v) => v.parse::<f32>()?.clamp(0., 1.),
+                None => 1.,
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RenderOpt {
+    pub output: String,
+    pub sample_rate: u32,
+    pub bit_depth: u16,
+    pub float: bool,
+    pub notes: Vec<NoteEvent>,
+    pub midi_file: Option<String>,
+    pub preset: Option<String>,
+    // Seconds rendered after the last note-off, so releases can ring out
+    pub tail: f32,
+}
+
+impl RenderOpt {
+    pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("render")
+            .about("Renders notes into a wav file without opening the GUI or an audio device")
+            .arg_from_usage("<OUTPUT> 'Wav file to write'")
+            .arg(
+                Arg::from_usage("-n, --note [NOTE]... 'Note to play, as note:start:length[:velocity] with times in seconds'")
+                    .number_of_values(1),
+            )
+            .arg_from_usage("-m, --midi [FILE] 'Standard MIDI file to play, on top of any --note'")
+            .arg_from_usage("-p, --preset [FILE] 'Preset to render with instead of the default patch'")
+            .arg_from_usage("-r, --sample-rate [RATE] 'Sample rate of the output, 8000 to 384000, defaults to 48000'")
+            .arg_from_usage("-b, --bit-depth [BITS] '16, 24 or 32, defaults to 16'")
+            .arg_from_usage("-f, --float 'Write 32 bit float samples instead of integers'")
+            .arg_from_usage("-t, --tail [SECONDS] 'Time rendered after the last note ends, defaults to 2'")
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Result<RenderOpt, anyhow::Error> {
+        let notes = match matches.values_of("note") {
+            Some(notes) => notes.map(str::parse).collect::<Result<Vec<_>, _>>()?,
+            None => vec![],
+        };
+
+        let float = matches.is_present("float");
+        let bit_depth = match matches.value_of("bit-depth") {
+            Some(bits) => bits.parse()?,
+            None if float => 32,
+            None => 16,
+        };
+
+        match (bit_depth, float) {
+            (16, false) | (24, false) | (32, _) => {}
+            _ => return Err(anyhow!("Unsupported bit depth {}", bit_depth)),
+        }
+
+        let sample_rate = match matches.value_of("sample-rate") {
+            Some(rate) => rate.parse()?,
+            None => 48000,
+        };
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+            return Err(anyhow!(
+                "Unsupported sample rate {}, expected {} to {}",
+                sample_rate,
+                MIN_SAMPLE_RATE,
+                MAX_SAMPLE_RATE
+            ));
+        }
+
+        Ok(RenderOpt {
+            output: matches.value_of("OUTPUT").unwrap().to_string(),
+            sample_rate,
+            bit_depth,
+            float,
+            notes,
+            midi_file: matches.value_of("midi").map(String::from),
+            preset: matches.value_of("preset").map(String::from),
+            tail: match matches.value_of("tail") {
+                Some(tail) => tail.parse()?,
+                None => 2.,
+            },
+        })
+    }
+
+    fn get_spec(&self, channels: u16) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: self.bit_depth,
+            sample_format: if self.float {
+                SampleFormat::Float
+            } else {
+                SampleFormat::Int
+            },
+        }
+    }
+}
+
+/// Turns the note list into note on/off messages, keyed by the frame they happen at
+fn schedule_notes(notes: &[NoteEvent], sample_rate: u32) -> Vec<(u64, Message)> {
+    let to_frames = |seconds: f32| (seconds.max(0.) * sample_rate as f32) as u64;
+
+    let mut events: Vec<(u64, Message)> = notes
+        .iter()
+        .flat_map(|n| {
+            vec![
+                (to_frames(n.start), Message::NoteOn(n.note, n.velocity)),
+                (to_frames(n.start + n.length), Message::NoteOff(n.note)),
+            ]
+        })
+        .collect();
+
+    // Stable, so a note-off and note-on at the same time stay in the order they were given
+    events.sort_by_key(|(frame, _)| *frame);
+    events
+}
+
+/// Drives the synth through the scheduled messages and writes the result.
+/// The synth has to be built for the same sample rate as the one in opt.
+pub fn render(opt: &RenderOpt, synth: Synth) -> Result<(), anyhow::Error> {
+    let mut events = schedule_notes(&opt.notes, opt.sample_rate);
+    if let Some(midi_file) = &opt.midi_file {
+        events.extend(load_midi_file(midi_file, opt.sample_rate)?);
+    }
+    let last_frame = events.iter().map(|(frame, _)| *frame).max().unwrap_or(0);
+    let total_frames = last_frame + (opt.tail.max(0.) * opt.sample_rate as f32) as u64;
+
+    let spec = opt.get_spec(synth.get_channels());
+    let mut writer = WavWriter::create(&opt.output, spec)?;
+
+    info!(
+        "Rendering {} events, {} frames into {}",
+        events.len(),
+        total_frames,
+        opt.output
+    );
+
+    render_events(synth, events, total_frames, |sample| {
+        if opt.float {
+            writer.write_sample(sample)
+        } else {
+            let max = ((1_i64 << (opt.bit_depth - 1)) - 1) as f32;
+            writer.write_sample((sample.clamp(-1., 1.) * max) as i32)
+        }
+    })?;
+
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Plays the messages at their frames and hands every sample over to write_sample
+pub fn render_events<F, E>(
+    mut synth: Synth,
+    events: Vec<(u64, Message)>,
+    total_frames: u64,
+    mut write_sample: F,
+) -> Result<(), E>
+where
+    F: FnMut(f32) -> Result<(), E>,
+{
+    synth.play_sequence(events);
+
+    let mut buffer = vec![0.; synth.get_block_size()];
+    let mut remaining = total_frames * synth.get_channels() as u64;
+    while remaining > 0 {
+        let block = &mut buffer[..remaining.min(buffer.len() as u64) as usize];
+        synth.process(block);
+
+        for sample in block.iter() {
+            write_sample(*sample)?;
+        }
+        remaining -= block.len() as u64;
+    }
+
+    Ok(())
+}
@@ -1,54 +1,49 @@
 //! ADSR envelope adapted from Yazz: https://github.com/icsga/Yazz
 //!
 
-use crate::get_sample_rate;
+use crate::DEFAULT_SAMPLE_RATE;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+/// Times are in seconds, sustain is a level from 0 to 1.
+///
+/// Curves go from -1 to 1. 0 is a straight line, above 0 moves fast at first and then
+/// slows down (exponential decays and releases), below 0 starts slow and speeds up.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ADSR {
     pub delay: f32,
     pub attack: f32,
+    // At full level between attack and decay
+    pub hold: f32,
     pub decay: f32,
     pub sustain: f32,
     pub release: f32,
+
+    pub attack_curve: f32,
+    pub decay_curve: f32,
+    pub release_curve: f32,
+
+    // 0 to 1, how much softer notes play quieter. At 1 the level follows the velocity
+    pub velocity_to_level: f32,
+    // -1 to 1, how much harder notes shorten attack, hold and decay. At 1 full velocity
+    // halves them and the softest notes double them, below 0 it's the other way around
+    pub velocity_to_time: f32,
 }
 
 impl Default for ADSR {
     fn default() -> Self {
         ADSR {
             delay: 0.0,
-            attack: 10000.,
-            decay: 5000.,
-            sustain: 100000.0,
-            release: 100000.,
-        }
-    }
-}
-
-impl ADSR {
-    pub fn convert_adsr_from_sample_clock(adsr: &ADSR) -> ADSR {
-        let sample_rate = get_sample_rate();
-        ADSR {
-            delay: adsr.delay / sample_rate,
-            attack: adsr.attack / sample_rate,
-            decay: adsr.decay / sample_rate,
-            sustain: adsr.sustain / sample_rate,
-            release: adsr.release / sample_rate,
-        }
-    }
-
-    pub fn convert_value_from_time(value: f32) -> f32 {
-        let sample_rate = get_sample_rate();
-        value * sample_rate
-    }
-
-    pub fn convert_adsr_from_time(adsr: &ADSR) -> ADSR {
-        let sample_rate = get_sample_rate();
-        ADSR {
-            delay: adsr.delay * sample_rate,
-            attack: adsr.attack * sample_rate,
-            decay: adsr.decay * sample_rate,
-            sustain: adsr.sustain * sample_rate,
-            release: adsr.release * sample_rate,
+            attack: 0.2,
+            hold: 0.0,
+            decay: 0.1,
+            sustain: 1.0,
+            release: 2.0,
+            attack_curve: 0.0,
+            decay_curve: 0.0,
+            release_curve: 0.0,
+            velocity_to_level: 1.0,
+            velocity_to_time: 0.0,
         }
     }
 }
@@ -58,19 +53,33 @@ pub enum ADSREnvelopeState {
     Idle,
     Delay,
     Attack,
+    Hold,
     Decay,
     Sustain,
     Release,
 }
 
+// How bent a curve of 1 is, as the exponent's factor
+const CURVE_STEEPNESS: f32 = 6.;
+
+#[derive(Clone)]
 pub struct ADSREnvelope {
     pub adsr_values: ADSR,
     state: ADSREnvelopeState,
+    sample_rate: f32,
 
-    start_time: u64,
-    end_time: u64,
+    // The segment of the current state, going from one level to another
+    segment_start: f32,
+    segment_end: f32,
+    segment_curve: f32,
+    // In frames, get_next_sample is called once for every one
+    segment_length: u64,
+    segment_position: u64,
+
+    // Velocity scaling of the note being played
+    peak_level: f32,
+    time_scale: f32,
 
-    increment: f32,
     last_value: f32,
 }
 
@@ -79,69 +88,77 @@ impl ADSREnvelope {
         ADSREnvelope {
             adsr_values,
             state: ADSREnvelopeState::Idle,
-            start_time: 0,
-            end_time: 0,
-            increment: 0.0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            segment_start: 0.0,
+            segment_end: 0.0,
+            segment_curve: 0.0,
+            segment_length: 0,
+            segment_position: 0,
+            peak_level: 1.0,
+            time_scale: 1.0,
             last_value: 0.0,
         }
     }
 
+    pub fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+    }
+
     pub fn is_active(&self) -> bool {
         self.state != ADSREnvelopeState::Idle
     }
 
+    pub fn get_last_value(&self) -> f32 {
+        self.last_value
+    }
+
+    /// Stops the envelope right away
     pub fn reset(&mut self) {
-        self.end_time = 0;
-        // self.state = ADSREnvelopeState::Idle;
+        self.change_state(ADSREnvelopeState::Idle);
     }
 
-    pub fn trigger(&mut self, sample_clock: u64) {
-        let sample_time = sample_clock - self.start_time;
+    /// velocity is 0 to 1, and scales the level and times as set in the ADSR values
+    pub fn trigger(&mut self, _sample_clock: u64, velocity: f32) {
+        let velocity = velocity.clamp(0., 1.);
+        let values = &self.adsr_values;
+        self.peak_level = 1. - values.velocity_to_level.clamp(0., 1.) * (1. - velocity);
+        self.time_scale = (-values.velocity_to_time * (2. * velocity - 1.)).exp2();
+
         let state = if self.adsr_values.delay > 0. {
             ADSREnvelopeState::Delay
         } else {
             ADSREnvelopeState::Attack
         };
-
-        self.change_state(state, sample_time);
+        self.change_state(state);
     }
 
-    pub fn release(&mut self, sample_clock: u64) {
-        let sample_time = sample_clock - self.start_time;
-        if self.state != ADSREnvelopeState::Release {
-            self.change_state(ADSREnvelopeState::Release, sample_time);
+    pub fn release(&mut self, _sample_clock: u64) {
+        if self.state != ADSREnvelopeState::Release && self.state != ADSREnvelopeState::Idle {
+            self.change_state(ADSREnvelopeState::Release);
         }
     }
 
-    pub fn get_next_sample(&mut self, sample_clock: u64) -> f32 {
-        let sample_time = sample_clock - self.start_time;
-
+    /// Moves the envelope on by one frame and returns its level there
+    pub fn get_next_sample(&mut self, _sample_clock: u64) -> f32 {
         match self.state {
             ADSREnvelopeState::Idle => return 0.,
-            ADSREnvelopeState::Delay => {
-                if sample_time >= self.end_time {
-                    self.change_state(ADSREnvelopeState::Attack, sample_time);
-                }
-            }
-            ADSREnvelopeState::Attack => {
-                self.last_value += self.increment;
-                if sample_time >= self.end_time {
-                    self.change_state(ADSREnvelopeState::Decay, sample_time);
-                }
-            }
-            ADSREnvelopeState::Decay => {
-                self.last_value += self.increment;
-                if sample_time >= self.end_time {
-                    self.change_state(ADSREnvelopeState::Sustain, sample_time);
-                }
-            }
             ADSREnvelopeState::Sustain => {
-                self.last_value = self.adsr_values.sustain;
+                self.last_value = self.adsr_values.sustain * self.peak_level;
             }
-            ADSREnvelopeState::Release => {
-                self.last_value += self.increment;
-                if sample_time >= self.end_time {
-                    self.change_state(ADSREnvelopeState::Idle, sample_time);
+            _ => {
+                self.segment_position += 1;
+                let progress = if self.segment_length == 0 {
+                    1.
+                } else {
+                    (self.segment_position as f32 / self.segment_length as f32).min(1.)
+                };
+
+                let shape = curve(progress, self.segment_curve);
+                self.last_value =
+                    self.segment_start + (self.segment_end - self.segment_start) * shape;
+
+                if self.segment_position >= self.segment_length {
+                    self.next_state();
                 }
             }
         };
@@ -150,38 +167,184 @@ impl ADSREnvelope {
         self.last_value
     }
 
-    fn change_state(&mut self, new_state: ADSREnvelopeState, sample_time: u64) {
+    fn next_state(&mut self) {
+        let next = match self.state {
+            ADSREnvelopeState::Delay => ADSREnvelopeState::Attack,
+            ADSREnvelopeState::Attack if self.adsr_values.hold > 0. => ADSREnvelopeState::Hold,
+            ADSREnvelopeState::Attack | ADSREnvelopeState::Hold => ADSREnvelopeState::Decay,
+            ADSREnvelopeState::Decay => ADSREnvelopeState::Sustain,
+            ADSREnvelopeState::Release => ADSREnvelopeState::Idle,
+            state => state,
+        };
+        self.change_state(next);
+    }
+
+    fn change_state(&mut self, new_state: ADSREnvelopeState) {
         self.state = new_state;
+        let values = self.adsr_values;
+        let peak = self.peak_level;
+        let scale = self.time_scale;
+
         match new_state {
             ADSREnvelopeState::Idle => self.last_value = 0.,
             ADSREnvelopeState::Delay => {
-                self.last_value = 0.;
-                self.end_time = sample_time + self.adsr_values.delay as u64;
+                // A note retriggered while it's still sounding fades out over the delay
+                // instead of cutting off
+                let from = self.last_value;
+                self.set_segment(from, 0., 0., values.delay);
             }
             ADSREnvelopeState::Attack => {
-                self.set_increment(0., 1., self.adsr_values.attack);
-
-                // Accounting for if we didn't start at zero
-                let frac = 1.0 - self.last_value;
-                self.set_end_time(sample_time, self.adsr_values.attack * frac);
+                // Accounting for if we didn't start at zero. Softer notes over a louder one
+                // ramp down to their peak, in the same time it would take to go up that far
+                let furthest = self.last_value.max(peak);
+                let frac = if furthest > 0. {
+                    (self.last_value - peak).abs() / furthest
+                } else {
+                    0.
+                };
+                let from = self.last_value;
+                self.set_segment(
+                    from,
+                    peak,
+                    values.attack_curve,
+                    values.attack * scale * frac,
+                );
             }
+            ADSREnvelopeState::Hold => self.set_segment(peak, peak, 0., values.hold * scale),
             ADSREnvelopeState::Decay => {
-                self.set_increment(1., self.adsr_values.sustain, self.adsr_values.decay);
-                self.set_end_time(sample_time, self.adsr_values.decay);
+                let sustain = values.sustain * peak;
+                self.set_segment(peak, sustain, values.decay_curve, values.decay * scale);
             }
             ADSREnvelopeState::Sustain => {}
             ADSREnvelopeState::Release => {
-                self.set_increment(self.last_value, 0., self.adsr_values.release);
-                self.set_end_time(sample_time, self.adsr_values.release);
+                let from = self.last_value;
+                self.set_segment(from, 0., values.release_curve, values.release);
             }
         }
     }
 
-    fn set_increment(&mut self, from: f32, to: f32, dur: f32) {
-        self.increment = (to - from) / dur;
+    // duration is in seconds
+    fn set_segment(&mut self, from: f32, to: f32, curve: f32, duration: f32) {
+        self.segment_start = from;
+        self.segment_end = to;
+        self.segment_curve = curve;
+        self.segment_length = (duration.max(0.) * self.sample_rate) as u64;
+        self.segment_position = 0;
+    }
+}
+
+// Bends progress through a segment (0 to 1) by the curve, see ADSR
+fn curve(progress: f32, curve: f32) -> f32 {
+    let k = curve.clamp(-1., 1.) * CURVE_STEEPNESS;
+    if k.abs() < 1e-3 {
+        return progress;
+    }
+    (1. - (-k * progress).exp()) / (1. - (-k).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Makes every time below a whole number of frames
+    const SAMPLE_RATE: f32 = 1024.;
+
+    fn prepared(adsr_values: ADSR) -> ADSREnvelope {
+        let mut envelope = ADSREnvelope::new(adsr_values);
+        envelope.prepare(SAMPLE_RATE, 0);
+        envelope
+    }
+
+    fn run(envelope: &mut ADSREnvelope, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|t| envelope.get_next_sample(t as u64))
+            .collect()
+    }
+
+    #[test]
+    fn holds_for_its_length_in_frames() {
+        // 32 frames of attack, then 64 of hold
+        let mut envelope = prepared(ADSR {
+            attack: 0.03125,
+            hold: 0.0625,
+            decay: 0.03125,
+            sustain: 0.5,
+            ..ADSR::default()
+        });
+        envelope.trigger(0, 1.);
+
+        let values = run(&mut envelope, 128);
+        assert!(values[30] < 1.);
+        assert!(values[31..96].iter().all(|&v| v == 1.));
+        assert!(values[96] < 1.);
+    }
+
+    #[test]
+    fn curves_bend_the_middle_of_a_segment() {
+        let midpoint = |attack_curve: f32| {
+            let mut envelope = prepared(ADSR {
+                attack: 0.0625,
+                attack_curve,
+                ..ADSR::default()
+            });
+            envelope.trigger(0, 1.);
+            run(&mut envelope, 32)[31]
+        };
+
+        let exponential = midpoint(1.);
+        let linear = midpoint(0.);
+        let logarithmic = midpoint(-1.);
+
+        assert!((linear - 0.5).abs() < 1e-6, "linear is at {}", linear);
+        assert!(exponential > 0.9, "exponential is at {}", exponential);
+        assert!(logarithmic < 0.1, "logarithmic is at {}", logarithmic);
+        assert!((exponential + logarithmic - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn velocity_scales_the_peak_and_the_times() {
+        let adsr = ADSR {
+            attack: 0.0625,
+            decay: 0.,
+            sustain: 1.,
+            velocity_to_level: 1.,
+            velocity_to_time: 1.,
+            ..ADSR::default()
+        };
+
+        // Full velocity plays at full level, with half the attack time
+        let mut envelope = prepared(adsr);
+        envelope.trigger(0, 1.);
+        let values = run(&mut envelope, 128);
+        assert!(values[30] < 1.);
+        assert_eq!(values[31], 1.);
+
+        // Half velocity peaks at half the level, with the attack time as set
+        let mut envelope = prepared(adsr);
+        envelope.trigger(0, 0.5);
+        let values = run(&mut envelope, 128);
+        assert!(values[62] < 0.5);
+        assert_eq!(values[63], 0.5);
+        assert!(values.iter().all(|&v| v <= 0.5));
     }
 
-    fn set_end_time(&mut self, sample_time: u64, end_time: f32) {
-        self.end_time = sample_time + end_time as u64;
+    #[test]
+    fn delayed_retriggers_fade_out_first() {
+        let mut envelope = prepared(ADSR {
+            delay: 0.0625,
+            attack: 0.,
+            decay: 0.,
+            sustain: 1.,
+            ..ADSR::default()
+        });
+        envelope.trigger(0, 1.);
+        run(&mut envelope, 128);
+        assert_eq!(envelope.get_last_value(), 1.);
+
+        envelope.trigger(128, 1.);
+        let values = run(&mut envelope, 64);
+        assert!(values[0] > 0.9, "jumped to {}", values[0]);
+        assert!(values.windows(2).all(|w| w[1] <= w[0]));
+        assert_eq!(values[63], 0.);
     }
 }
@@ -1,25 +1,38 @@
 //! Assorted filters, mostly adapted from oxcable: https://github.com/oxcable/oxcable
 //!   and JUCE: https://juce.com/
 
-use crate::get_sample_rate;
+use crate::DEFAULT_SAMPLE_RATE;
 use std::f32::consts::PI;
 
-use crate::lfo::{Lfo, LfoStatePacket};
+use crate::lfo::{map_octaves, Lfo, LfoStatePacket, LFO_PARAM_INFO};
+use crate::smoothing::{SmoothedValue, Smoothing};
+use crate::{ParamInfo, Processor, Unit};
 use num_traits::FloatConst;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-pub enum Filter {
-    IIRLowPassFilter(IIRLowPassFilter),
-    StateVariableTPTFilter(StateVariableTPTFilter),
-}
-
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum FilterType {
     LowPass,
     HighPass,
     BandPass,
 }
 
+impl FilterType {
+    /// Filter types are stepped parameters, numbered in declaration order
+    pub fn from_param(value: f32) -> FilterType {
+        match value.round() as i32 {
+            1 => FilterType::HighPass,
+            2 => FilterType::BandPass,
+            _ => FilterType::LowPass,
+        }
+    }
+
+    pub fn to_param(self) -> f32 {
+        self as i32 as f32
+    }
+}
+
 impl Display for FilterType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -30,82 +43,242 @@ impl Display for FilterType {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct IIRFilterStatePacket {
     pub filter_type: FilterType,
     pub frequency: f32,
     pub q: f32,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+impl Default for IIRFilterStatePacket {
+    fn default() -> Self {
+        IIRFilterStatePacket {
+            filter_type: FilterType::LowPass,
+            frequency: 15000.,
+            q: 1.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct StateVariableTPTFilterStatePacket {
     pub filter_type: FilterType,
     pub frequency: f32,
     pub resonance: f32,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+impl Default for StateVariableTPTFilterStatePacket {
+    fn default() -> Self {
+        StateVariableTPTFilterStatePacket {
+            filter_type: FilterType::LowPass,
+            frequency: 2000.,
+            resonance: 1.0 / f32::sqrt(2.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ModulatedFilterStatePacket {
     pub base_frequency: f32,
     pub filter: StateVariableTPTFilterStatePacket,
     pub lfo: LfoStatePacket,
+    // Octaves the LFO moves the cutoff up and down at full swing
+    pub lfo_depth: f32,
 }
 
+impl Default for ModulatedFilterStatePacket {
+    fn default() -> Self {
+        ModulatedFilterStatePacket {
+            base_frequency: 2000.,
+            filter: StateVariableTPTFilterStatePacket::default(),
+            lfo: LfoStatePacket::default(),
+            lfo_depth: DEFAULT_LFO_DEPTH,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct ModulatedFilter {
     pub lfo: Lfo,
-    pub filter: Filter,
+    pub filter: StateVariableTPTFilter,
     base_frequency: f32,
+    lfo_depth: f32,
 }
 
+/// Octaves of cutoff sweep at full LFO swing, unless set otherwise
+pub const DEFAULT_LFO_DEPTH: f32 = 2.;
+
+pub const FILTER_TYPE_NAMES: &[&str] = &["Low pass", "High pass", "Band pass"];
+
+static MODULATED_FILTER_PARAMS: [ParamInfo; 11] = [
+    ParamInfo::new("Base frequency", 0., 15_000., 2000.)
+        .with_unit(Unit::Hertz)
+        .with_skew(0.3),
+    ParamInfo::new("Filter type", 0., 2., 0.).with_unit(Unit::Choice(FILTER_TYPE_NAMES)),
+    ParamInfo::new(
+        "Resonance",
+        MIN_RESONANCE,
+        1.,
+        std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    LFO_PARAM_INFO[Lfo::WAVEFORM],
+    LFO_PARAM_INFO[Lfo::FREQUENCY],
+    LFO_PARAM_INFO[Lfo::PHASE],
+    LFO_PARAM_INFO[Lfo::MODE],
+    LFO_PARAM_INFO[Lfo::POLARITY],
+    LFO_PARAM_INFO[Lfo::FADE_IN],
+    LFO_PARAM_INFO[Lfo::SYNC],
+    ParamInfo::new("LFO depth", 0., 8., DEFAULT_LFO_DEPTH),
+];
+
 impl ModulatedFilter {
-    pub fn new(lfo: Lfo, filter: Filter, base_frequency: f32) -> ModulatedFilter {
+    pub const BASE_FREQUENCY: usize = 0;
+    pub const FILTER_TYPE: usize = 1;
+    pub const RESONANCE: usize = 2;
+    // The LFO's own parameters follow in the same order, see Lfo::params
+    pub const LFO_TYPE: usize = 3;
+    pub const LFO_FREQUENCY: usize = 4;
+    pub const LFO_PHASE: usize = 5;
+    pub const LFO_MODE: usize = 6;
+    pub const LFO_POLARITY: usize = 7;
+    pub const LFO_FADE_IN: usize = 8;
+    pub const LFO_SYNC: usize = 9;
+    pub const LFO_DEPTH: usize = 10;
+
+    pub fn new(lfo: Lfo, filter: StateVariableTPTFilter, base_frequency: f32) -> ModulatedFilter {
         ModulatedFilter {
             lfo,
             filter,
             base_frequency,
+            lfo_depth: DEFAULT_LFO_DEPTH,
         }
     }
 
+    pub fn with_lfo_depth(mut self, octaves: f32) -> ModulatedFilter {
+        self.lfo_depth = octaves;
+        self
+    }
+
     pub fn set_frequency(&mut self, new_frequency: f32) {
         self.base_frequency = new_frequency;
-        match &mut self.filter {
-            Filter::IIRLowPassFilter(filter) => {
-                filter.set_frequency(get_sample_rate(), new_frequency);
-            }
-            Filter::StateVariableTPTFilter(filter) => {
-                filter.set_frequency(get_sample_rate(), new_frequency);
-            }
-        }
+        self.filter.set_frequency(new_frequency);
     }
+}
 
-    pub fn get_state_packet(&self) -> ModulatedFilterStatePacket {
-        let filter_state_packet = match &self.filter {
-            Filter::StateVariableTPTFilter(f) => f.get_state_packet(),
-            Filter::IIRLowPassFilter(_) => unimplemented!(),
+impl Processor for ModulatedFilter {
+    type StatePacket = ModulatedFilterStatePacket;
+
+    fn from_state_packet(packet: &ModulatedFilterStatePacket, sample_rate: f32) -> ModulatedFilter {
+        let base_frequency =
+            MODULATED_FILTER_PARAMS[Self::BASE_FREQUENCY].clamp(packet.base_frequency);
+        // The LFO goes on top of the base frequency, whatever cutoff the filter was saved with
+        let filter = StateVariableTPTFilterStatePacket {
+            frequency: base_frequency,
+            ..packet.filter
         };
+        ModulatedFilter::new(
+            Lfo::from_state_packet(&packet.lfo),
+            StateVariableTPTFilter::from_state_packet(&filter, sample_rate),
+            base_frequency,
+        )
+        .with_lfo_depth(MODULATED_FILTER_PARAMS[Self::LFO_DEPTH].clamp(packet.lfo_depth))
+    }
 
-        ModulatedFilterStatePacket {
-            base_frequency: self.base_frequency,
-            filter: filter_state_packet,
-            lfo: self.lfo.get_state_packet(),
+    fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        self.lfo.prepare(sample_rate, max_block);
+        self.filter.prepare(sample_rate, max_block);
+    }
+
+    fn reset(&mut self) {
+        self.lfo.reset();
+        self.filter.reset();
+    }
+
+    fn set_smoothing_time(&mut self, seconds: f32) {
+        self.filter.set_smoothing_time(seconds);
+    }
+
+    fn trigger(&mut self, sample_clock: u64) {
+        self.lfo.trigger(sample_clock);
+    }
+
+    fn set_tempo(&mut self, bpm: f32) {
+        self.lfo.set_tempo(bpm);
+    }
+
+    fn set_channels(&mut self, channels: u16) {
+        self.lfo.set_channels(channels);
+    }
+
+    fn process_samples(&mut self, sample_clock: u64, samples: &mut [f32]) {
+        // The LFO moves the cutoff every sample, stepping it once per block would be heard
+        // as zipper noise on fast sweeps
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let value = self.lfo.get_value(sample_clock + i as u64);
+            let ratio = map_octaves(value, 1., self.lfo_depth);
+            self.filter.set_cutoff_modulation(ratio);
+            *sample = self.filter.process_sample(*sample);
         }
     }
 
-    pub fn process_samples(&mut self, sample_clock: u64, samples: &mut [f32]) {
-        let freq = self.base_frequency * self.lfo.get_sample(sample_clock, 1.0, 20_000.0);
-        match &mut self.filter {
-            Filter::IIRLowPassFilter(filter) => {
-                filter.set_frequency(get_sample_rate(), freq);
-                filter.process_samples(sample_clock, samples);
-            }
-            Filter::StateVariableTPTFilter(filter) => {
-                filter.set_frequency(get_sample_rate(), freq);
-                filter.process_samples(sample_clock, samples);
+    fn params(&self) -> &'static [ParamInfo] {
+        &MODULATED_FILTER_PARAMS
+    }
+
+    fn get_param(&self, id: usize) -> Option<f32> {
+        match id {
+            Self::BASE_FREQUENCY => Some(self.base_frequency),
+            Self::FILTER_TYPE => self.filter.get_param(StateVariableTPTFilter::FILTER_TYPE),
+            Self::RESONANCE => self.filter.get_param(StateVariableTPTFilter::RESONANCE),
+            Self::LFO_TYPE..=Self::LFO_SYNC => self.lfo.get_param(id - Self::LFO_TYPE),
+            Self::LFO_DEPTH => Some(self.lfo_depth),
+            _ => None,
+        }
+    }
+
+    fn set_param(&mut self, id: usize, value: f32) {
+        match id {
+            Self::BASE_FREQUENCY => self.set_frequency(value),
+            Self::FILTER_TYPE => self
+                .filter
+                .set_param(StateVariableTPTFilter::FILTER_TYPE, value),
+            Self::RESONANCE => self
+                .filter
+                .set_param(StateVariableTPTFilter::RESONANCE, value),
+            Self::LFO_TYPE..=Self::LFO_SYNC => self.lfo.set_param(id - Self::LFO_TYPE, value),
+            Self::LFO_DEPTH => self.lfo_depth = value,
+            _ => {}
+        }
+    }
+
+    fn set_param_immediate(&mut self, id: usize, value: f32) {
+        match id {
+            Self::BASE_FREQUENCY => {
+                self.base_frequency = value;
+                self.filter
+                    .set_param_immediate(StateVariableTPTFilter::FREQUENCY, value);
             }
+            Self::RESONANCE => self
+                .filter
+                .set_param_immediate(StateVariableTPTFilter::RESONANCE, value),
+            _ => self.set_param(id, value),
+        }
+    }
+
+    fn get_state_packet(&self) -> ModulatedFilterStatePacket {
+        ModulatedFilterStatePacket {
+            base_frequency: self.base_frequency,
+            filter: self.filter.get_state_packet(),
+            lfo: self.lfo.get_state_packet(),
+            lfo_depth: self.lfo_depth,
         }
     }
 }
 
+#[derive(Clone)]
 pub struct StateVariableTPTFilter {
     g: f32,
     h: f32,
@@ -114,13 +287,21 @@ pub struct StateVariableTPTFilter {
     s2: Vec<f32>,
 
     filter_type: FilterType,
-    cutoff_frequency: f32,
-    resonance: f32,
+    // The coefficients follow these as they ramp to new values
+    cutoff_frequency: SmoothedValue,
+    resonance: SmoothedValue,
+    // Frequency ratio applied on top of the cutoff, e.g. by an LFO
+    cutoff_modulation: f32,
+    sample_rate: f32,
 
     channels: u16,
 }
 
 impl StateVariableTPTFilter {
+    pub const FILTER_TYPE: usize = 0;
+    pub const FREQUENCY: usize = 1;
+    pub const RESONANCE: usize = 2;
+
     pub fn new(
         sample_rate: f32,
         cutoff_frequency: f32,
@@ -133,27 +314,43 @@ impl StateVariableTPTFilter {
             s1: vec![2., 2.], // TODO: these are supposed be as big as # of channels (?)
             s2: vec![2., 2.],
             filter_type,
-            cutoff_frequency,
-            resonance: 1.0 / f32::sqrt(2.0),
+            cutoff_frequency: SmoothedValue::new(cutoff_frequency, Smoothing::Exponential),
+            resonance: SmoothedValue::new(1.0 / f32::sqrt(2.0), Smoothing::Linear),
+            cutoff_modulation: 1.,
+            sample_rate,
             channels: 1,
         };
-        s.set_frequency(sample_rate, cutoff_frequency);
+        s.prepare(sample_rate, 0);
         s
     }
 
-    pub fn set_frequency(&mut self, sample_rate: f32, new_frequency: f32) {
-        self.cutoff_frequency = new_frequency;
+    /// Ramps to the new frequency over the smoothing time
+    pub fn set_frequency(&mut self, new_frequency: f32) {
+        self.cutoff_frequency.set_target(new_frequency);
+        self.update_coefficients();
+    }
 
-        self.g = (f32::PI() * self.cutoff_frequency / sample_rate).tan();
-        self.r2 = 1.0 / self.resonance;
-        self.h = 1.0 / (1.0 + self.r2 * self.g + self.g * self.g);
+    pub fn set_resonance(&mut self, new_resonance: f32) {
+        self.resonance.set_target(new_resonance);
+        self.update_coefficients();
     }
 
-    pub fn set_resonance(&mut self, sample_rate: f32, new_resonance: f32) {
-        self.resonance = new_resonance;
+    /// Multiplies the cutoff by ratio right away, without smoothing. Meant to be called
+    /// for every sample by whatever is modulating the filter
+    #[inline(always)]
+    pub fn set_cutoff_modulation(&mut self, ratio: f32) {
+        if ratio != self.cutoff_modulation {
+            self.cutoff_modulation = ratio;
+            self.update_coefficients();
+        }
+    }
 
-        self.g = (f32::PI() * self.cutoff_frequency / sample_rate).tan();
-        self.r2 = 1.0 / self.resonance;
+    fn update_coefficients(&mut self) {
+        // The coefficient goes off to infinity at Nyquist
+        let cutoff_frequency = (self.cutoff_frequency.get_current() * self.cutoff_modulation)
+            .min(self.sample_rate * MAX_CUTOFF_RATIO);
+        self.g = (f32::PI() * cutoff_frequency / self.sample_rate).tan();
+        self.r2 = 1.0 / self.resonance.get_current().max(MIN_RESONANCE);
         self.h = 1.0 / (1.0 + self.r2 * self.g + self.g * self.g);
     }
 
@@ -161,41 +358,148 @@ impl StateVariableTPTFilter {
         self.filter_type = filter_type;
     }
 
-    pub fn get_state_packet(&self) -> StateVariableTPTFilterStatePacket {
-        StateVariableTPTFilterStatePacket {
-            filter_type: self.filter_type,
-            frequency: self.cutoff_frequency,
-            resonance: self.resonance,
+    /// Filters a single sample, moving any parameter ramps along by one sample
+    #[inline(always)]
+    pub fn process_sample(&mut self, sample: f32) -> f32 {
+        if self.cutoff_frequency.is_smoothing() || self.resonance.is_smoothing() {
+            self.cutoff_frequency.get_next_value();
+            self.resonance.get_next_value();
+            self.update_coefficients();
+        }
+
+        let Self {
+            g, r2, channels, h, ..
+        } = *self;
+        let channels = channels as usize - 1;
+
+        let ls1 = self.s1[channels];
+        let ls2 = self.s1[channels];
+
+        let yhp = h * (sample - ls1 * (g * r2) - ls2);
+
+        let ybp = yhp * g + ls1;
+        self.s1[channels] = yhp * g + ybp;
+
+        let ylp = ybp * g + ls2;
+        self.s2[channels] = ybp * g + ylp;
+
+        match self.filter_type {
+            FilterType::LowPass => ylp,
+            FilterType::HighPass => ybp,
+            FilterType::BandPass => yhp,
         }
     }
+}
 
-    pub fn process_samples(&mut self, _samples_clock: u64, samples: &mut [f32]) {
-        for i in 0..samples.len() {
-            let Self {
-                g, r2, channels, h, ..
-            } = *self;
-            let channels = channels as usize - 1;
+// Highest cutoff as a proportion of the sample rate, just below Nyquist
+const MAX_CUTOFF_RATIO: f32 = 0.49;
+
+/// Lowest resonance the filters take, the damping is its inverse and blows up at 0
+pub const MIN_RESONANCE: f32 = 0.1;
+
+static STATE_VARIABLE_TPT_FILTER_PARAMS: [ParamInfo; 3] = [
+    ParamInfo::new("Filter type", 0., 2., 0.).with_unit(Unit::Choice(FILTER_TYPE_NAMES)),
+    ParamInfo::new("Frequency", 0., 15_000., 2000.)
+        .with_unit(Unit::Hertz)
+        .with_skew(0.3),
+    ParamInfo::new(
+        "Resonance",
+        MIN_RESONANCE,
+        1.,
+        std::f32::consts::FRAC_1_SQRT_2,
+    ),
+];
+
+impl Processor for StateVariableTPTFilter {
+    type StatePacket = StateVariableTPTFilterStatePacket;
+
+    fn from_state_packet(
+        packet: &StateVariableTPTFilterStatePacket,
+        sample_rate: f32,
+    ) -> StateVariableTPTFilter {
+        // Packets come from preset files, which can hold anything
+        let params = &STATE_VARIABLE_TPT_FILTER_PARAMS;
+        let mut filter = StateVariableTPTFilter::new(
+            sample_rate,
+            params[Self::FREQUENCY].clamp(packet.frequency),
+            packet.filter_type,
+        );
+        filter
+            .resonance
+            .set_immediate(params[Self::RESONANCE].clamp(packet.resonance));
+        filter.update_coefficients();
+        filter
+    }
 
-            let ls1 = self.s1[channels];
-            let ls2 = self.s1[channels];
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.cutoff_frequency.prepare(sample_rate);
+        self.resonance.prepare(sample_rate);
+        self.update_coefficients();
+    }
 
-            let yhp = h * (samples[i] - ls1 * (g * r2) - ls2);
+    fn reset(&mut self) {
+        self.s1.iter_mut().for_each(|s| *s = 0.);
+        self.s2.iter_mut().for_each(|s| *s = 0.);
+    }
 
-            let ybp = yhp * g + ls1;
-            self.s1[channels] = yhp * g + ybp;
+    fn set_smoothing_time(&mut self, seconds: f32) {
+        self.cutoff_frequency.set_ramp_time(seconds);
+        self.resonance.set_ramp_time(seconds);
+    }
 
-            let ylp = ybp * g + ls2;
-            self.s2[channels] = ybp * g + ylp;
+    fn process_samples(&mut self, _samples_clock: u64, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &STATE_VARIABLE_TPT_FILTER_PARAMS
+    }
 
-            samples[i] = match self.filter_type {
-                FilterType::LowPass => ylp,
-                FilterType::HighPass => ybp,
-                FilterType::BandPass => yhp,
+    fn get_param(&self, id: usize) -> Option<f32> {
+        match id {
+            Self::FILTER_TYPE => Some(self.filter_type.to_param()),
+            Self::FREQUENCY => Some(self.cutoff_frequency.get_target()),
+            Self::RESONANCE => Some(self.resonance.get_target()),
+            _ => None,
+        }
+    }
+
+    fn set_param(&mut self, id: usize, value: f32) {
+        match id {
+            Self::FILTER_TYPE => self.set_filter_type(FilterType::from_param(value)),
+            Self::FREQUENCY => self.set_frequency(value),
+            Self::RESONANCE => self.set_resonance(value),
+            _ => {}
+        }
+    }
+
+    fn set_param_immediate(&mut self, id: usize, value: f32) {
+        match id {
+            Self::FREQUENCY => {
+                self.cutoff_frequency.set_immediate(value);
+                self.update_coefficients();
+            }
+            Self::RESONANCE => {
+                self.resonance.set_immediate(value);
+                self.update_coefficients();
             }
+            _ => self.set_param(id, value),
+        }
+    }
+
+    fn get_state_packet(&self) -> StateVariableTPTFilterStatePacket {
+        StateVariableTPTFilterStatePacket {
+            filter_type: self.filter_type,
+            frequency: self.cutoff_frequency.get_target(),
+            resonance: self.resonance.get_target(),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct IIRLowPassFilter {
     v1: f32,
     v2: f32,
@@ -208,9 +512,13 @@ pub struct IIRLowPassFilter {
 
     pub frequency: f32,
     pub q: f32,
+    sample_rate: f32,
 }
 
 impl IIRLowPassFilter {
+    pub const FREQUENCY: usize = 0;
+    pub const Q: usize = 1;
+
     pub fn new(
         c1: f32,
         c2: f32,
@@ -235,12 +543,21 @@ impl IIRLowPassFilter {
 
             frequency,
             q,
+            sample_rate: DEFAULT_SAMPLE_RATE,
         }
     }
 
-    pub fn set_frequency(&mut self, sample_rate: f32, frequency: f32) {
-        let q = 1.0;
-        let n = 1. / (PI * frequency / sample_rate).tan();
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+        self.update_coefficients();
+    }
+
+    // Leaves the filter's state alone, so the cutoff can move while it's playing without
+    // clicking. The tangent goes off to infinity at Nyquist
+    fn update_coefficients(&mut self) {
+        let frequency = self.frequency.min(self.sample_rate * MAX_LOW_PASS_RATIO);
+        let q = self.q;
+        let n = 1. / (PI * frequency / self.sample_rate).tan();
         let n_squared = n * n;
         let c1_base = 1. / (1. + 1. / q * n + n_squared);
 
@@ -253,8 +570,6 @@ impl IIRLowPassFilter {
 
         let a = 1.0 / c4; // TODO: this is pointless for low pass bc c4 is hard coded to 1.
 
-        self.v1 = 0.;
-        self.v2 = 0.;
         self.c0 = c1 * a;
         self.c1 = c2 * a;
         self.c2 = c3 * a;
@@ -271,7 +586,7 @@ impl IIRLowPassFilter {
         let n_squared = n * n;
         let c1 = 1. / (1. + 1. / q * n + n_squared);
 
-        IIRLowPassFilter::new(
+        let mut filter = IIRLowPassFilter::new(
             c1,
             c1 * 2.0,
             c1,
@@ -280,10 +595,50 @@ impl IIRLowPassFilter {
             c1 * (1. - 1. / q * n + n_squared),
             frequency,
             q,
-        )
+        );
+        filter.sample_rate = sample_rate;
+        filter
     }
 
-    pub fn process_samples(&mut self, _samples_clock: u64, samples: &mut [f32]) {
+    pub fn set_q(&mut self, q: f32) {
+        self.q = q;
+        self.update_coefficients();
+    }
+}
+
+static IIR_LOW_PASS_FILTER_PARAMS: [ParamInfo; 2] = [
+    ParamInfo::new("Frequency", 20., 15_000., 15_000.)
+        .with_unit(Unit::Hertz)
+        .with_skew(0.3),
+    ParamInfo::new("Q", 0.1, 10., 1.),
+];
+
+// Highest cutoff as a share of the sample rate, new_low_pass only goes up to Nyquist
+const MAX_LOW_PASS_RATIO: f32 = 0.45;
+
+impl Processor for IIRLowPassFilter {
+    type StatePacket = IIRFilterStatePacket;
+
+    fn from_state_packet(packet: &IIRFilterStatePacket, sample_rate: f32) -> IIRLowPassFilter {
+        // Packets come from preset files, which can hold anything
+        let frequency = IIR_LOW_PASS_FILTER_PARAMS[Self::FREQUENCY]
+            .clamp(packet.frequency)
+            .min(sample_rate * MAX_LOW_PASS_RATIO);
+        let q = IIR_LOW_PASS_FILTER_PARAMS[Self::Q].clamp(packet.q);
+        IIRLowPassFilter::new_low_pass(sample_rate, frequency, q)
+    }
+
+    fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+    }
+
+    fn reset(&mut self) {
+        self.v1 = 0.;
+        self.v2 = 0.;
+    }
+
+    fn process_samples(&mut self, _samples_clock: u64, samples: &mut [f32]) {
         let IIRLowPassFilter {
             v1,
             v2,
@@ -312,7 +667,27 @@ impl IIRLowPassFilter {
         self.v2 = lv2;
     }
 
-    pub fn get_state_packet(&self) -> IIRFilterStatePacket {
+    fn params(&self) -> &'static [ParamInfo] {
+        &IIR_LOW_PASS_FILTER_PARAMS
+    }
+
+    fn get_param(&self, id: usize) -> Option<f32> {
+        match id {
+            Self::FREQUENCY => Some(self.frequency),
+            Self::Q => Some(self.q),
+            _ => None,
+        }
+    }
+
+    fn set_param(&mut self, id: usize, value: f32) {
+        match id {
+            Self::FREQUENCY => self.set_frequency(value),
+            Self::Q => self.set_q(value),
+            _ => {}
+        }
+    }
+
+    fn get_state_packet(&self) -> IIRFilterStatePacket {
         IIRFilterStatePacket {
             filter_type: FilterType::LowPass,
             frequency: self.frequency,
@@ -320,3 +695,24 @@ impl IIRLowPassFilter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_pass_keeps_its_state_below_nyquist() {
+        let mut filter = IIRLowPassFilter::new_low_pass(48000., 15000., 1.);
+        let mut samples = [1.; 64];
+        filter.process_samples(0, &mut samples);
+        let state = (filter.v1, filter.v2);
+
+        // 15kHz is past Nyquist at 22050
+        filter.prepare(22050., 64);
+        filter.set_frequency(12000.);
+        assert_eq!((filter.v1, filter.v2), state);
+
+        filter.process_samples(64, &mut samples);
+        assert!(samples.iter().all(|s| s.is_finite()));
+    }
+}
@@ -1,40 +1,373 @@
-use crate::get_sample_rate;
+use crate::{ParamInfo, Unit, DEFAULT_SAMPLE_RATE};
 use num_traits::FloatConst;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+/// Tempo LFOs synced to note divisions follow until they're told otherwise, in BPM
+pub const DEFAULT_TEMPO: f32 = 120.;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum LfoType {
     Sine,
     Saw,
     Square,
+    Triangle,
+    RampDown,
+    // A new random value every cycle
+    SampleAndHold,
+    // Glides from one random value to the next over every cycle
+    SmoothRandom,
+}
+
+pub const LFO_TYPE_NAMES: &[&str] = &[
+    "Sine",
+    "Saw",
+    "Square",
+    "Triangle",
+    "Ramp down",
+    "Sample & hold",
+    "Smooth random",
+];
+
+impl LfoType {
+    /// Waveforms are stepped parameters, numbered in declaration order
+    pub fn from_param(value: f32) -> LfoType {
+        match value.round() as i32 {
+            1 => LfoType::Saw,
+            2 => LfoType::Square,
+            3 => LfoType::Triangle,
+            4 => LfoType::RampDown,
+            5 => LfoType::SampleAndHold,
+            6 => LfoType::SmoothRandom,
+            _ => LfoType::Sine,
+        }
+    }
+
+    pub fn to_param(self) -> f32 {
+        self as i32 as f32
+    }
+}
+
+/// What happens to the LFO when a note starts
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LfoMode {
+    // Keeps going, only the fade-in starts over
+    FreeRunning,
+    // Starts over from the start phase
+    Retrigger,
+    // Starts over, runs through a single cycle and stays at its end
+    OneShot,
+}
+
+pub const LFO_MODE_NAMES: &[&str] = &["Free running", "Retrigger", "One shot"];
+
+impl LfoMode {
+    pub fn from_param(value: f32) -> LfoMode {
+        match value.round() as i32 {
+            1 => LfoMode::Retrigger,
+            2 => LfoMode::OneShot,
+            _ => LfoMode::FreeRunning,
+        }
+    }
+
+    pub fn to_param(self) -> f32 {
+        self as i32 as f32
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LfoPolarity {
+    // -1 to 1
+    Bipolar,
+    // 0 to 1
+    Unipolar,
+}
+
+pub const LFO_POLARITY_NAMES: &[&str] = &["Bipolar", "Unipolar"];
+
+impl LfoPolarity {
+    pub fn from_param(value: f32) -> LfoPolarity {
+        match value.round() as i32 {
+            1 => LfoPolarity::Unipolar,
+            _ => LfoPolarity::Bipolar,
+        }
+    }
+
+    pub fn to_param(self) -> f32 {
+        self as i32 as f32
+    }
+}
+
+/// Length of one LFO cycle when it's synced to the tempo
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum NoteDivision {
+    FourBars,
+    TwoBars,
+    Bar,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+    DottedQuarter,
+    DottedEighth,
+    QuarterTriplet,
+    EighthTriplet,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+// Index 0 is "Off", the divisions follow in declaration order
+pub const LFO_SYNC_NAMES: &[&str] = &[
+    "Off",
+    "4 bars",
+    "2 bars",
+    "1 bar",
+    "1/2",
+    "1/4",
+    "1/8",
+    "1/16",
+    "1/32",
+    "1/4 dotted",
+    "1/8 dotted",
+    "1/4 triplet",
+    "1/8 triplet",
+];
+
+impl NoteDivision {
+    /// In quarter notes, assuming 4/4
+    pub fn beats(self) -> f32 {
+        match self {
+            NoteDivision::FourBars => 16.,
+            NoteDivision::TwoBars => 8.,
+            NoteDivision::Bar => 4.,
+            NoteDivision::Half => 2.,
+            NoteDivision::Quarter => 1.,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::ThirtySecond => 0.125,
+            NoteDivision::DottedQuarter => 1.5,
+            NoteDivision::DottedEighth => 0.75,
+            NoteDivision::QuarterTriplet => 2. / 3.,
+            NoteDivision::EighthTriplet => 1. / 3.,
+        }
+    }
+
+    /// 0 turns syncing off, see LFO_SYNC_NAMES
+    pub fn from_param(value: f32) -> Option<NoteDivision> {
+        let division = match value.round() as i32 {
+            1 => NoteDivision::FourBars,
+            2 => NoteDivision::TwoBars,
+            3 => NoteDivision::Bar,
+            4 => NoteDivision::Half,
+            5 => NoteDivision::Quarter,
+            6 => NoteDivision::Eighth,
+            7 => NoteDivision::Sixteenth,
+            8 => NoteDivision::ThirtySecond,
+            9 => NoteDivision::DottedQuarter,
+            10 => NoteDivision::DottedEighth,
+            11 => NoteDivision::QuarterTriplet,
+            12 => NoteDivision::EighthTriplet,
+            _ => return None,
+        };
+        Some(division)
+    }
+
+    pub fn to_param(division: Option<NoteDivision>) -> f32 {
+        division.map(|d| d as i32 + 1).unwrap_or(0) as f32
+    }
+}
+
+/// Maps an LFO value from -1..1 onto min..max
+pub fn map_range(value: f32, min: f32, max: f32) -> f32 {
+    min + (value + 1.) / 2. * (max - min)
+}
+
+/// Moves center up and down by depth at full swing
+pub fn map_linear(value: f32, center: f32, depth: f32) -> f32 {
+    center + value * depth
+}
+
+/// Multiplies base by up to 2^octaves at full swing, for frequencies and other values heard
+/// logarithmically. Use a base of 1 for a ratio to multiply with
+pub fn map_octaves(value: f32, base: f32, octaves: f32) -> f32 {
+    base * (value * octaves).exp2()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LfoStatePacket {
     pub waveform: LfoType,
+    // Cycles per second, unless synced
     pub frequency: f32,
+    // Where in the cycle it starts, 0 to 1
     pub phase: f32,
+    pub mode: LfoMode,
+    pub polarity: LfoPolarity,
+    // Seconds it takes to fade in after a note starts
+    pub fade_in: f32,
+    // Cycle length in notes at the host tempo, frequency is used when None
+    pub sync: Option<NoteDivision>,
+}
+
+impl Default for LfoStatePacket {
+    fn default() -> Self {
+        LfoStatePacket {
+            waveform: LfoType::Sine,
+            frequency: 0.,
+            phase: 0.,
+            mode: LfoMode::FreeRunning,
+            polarity: LfoPolarity::Bipolar,
+            fade_in: 0.,
+            sync: None,
+        }
+    }
 }
 
+// Shared by everything that embeds an LFO, so their parameters line up
+pub const LFO_PARAM_INFO: [ParamInfo; 7] = [
+    ParamInfo::new("LFO type", 0., 6., 0.).with_unit(Unit::Choice(LFO_TYPE_NAMES)),
+    ParamInfo::new("LFO frequency", 0., 20., 0.)
+        .with_unit(Unit::Hertz)
+        .with_skew(0.5),
+    ParamInfo::new("LFO phase", 0., 1., 0.),
+    ParamInfo::new("LFO mode", 0., 2., 0.).with_unit(Unit::Choice(LFO_MODE_NAMES)),
+    ParamInfo::new("LFO polarity", 0., 1., 0.).with_unit(Unit::Choice(LFO_POLARITY_NAMES)),
+    ParamInfo::new("LFO fade in", 0., 10., 0.)
+        .with_unit(Unit::Seconds)
+        .with_skew(0.5),
+    ParamInfo::new("LFO sync", 0., 12., 0.).with_unit(Unit::Choice(LFO_SYNC_NAMES)),
+];
+
+static LFO_PARAMS: [ParamInfo; 7] = LFO_PARAM_INFO;
+
+// Anything but 0 works, xorshift gets stuck there
+const RANDOM_SEED: u32 = 0x2545_f491;
+
+#[derive(Clone)]
 pub struct Lfo {
     pub waveform: LfoType,
     pub frequency: f32,
     pub phase: f32,
+    pub mode: LfoMode,
+    pub polarity: LfoPolarity,
+    pub fade_in: f32,
+    pub sync: Option<NoteDivision>,
 
+    sample_rate: f32,
+    // Samples the sample clock counts per frame, it runs once per channel
+    channels: u16,
+    tempo: f32,
     pos: f32,
     last_update: u64,
+    // Sample clock time of the last trigger, for the fade-in
+    triggered_at: u64,
+    // Cycles run through since the last trigger, one shots stop at 1
+    travelled: f32,
+    finished: bool,
+
+    // Random values the random waveforms move from and to, and the generator behind them
+    previous_random: f32,
+    next_random: f32,
+    random_state: u32,
 }
 
 impl Lfo {
+    pub const WAVEFORM: usize = 0;
+    pub const FREQUENCY: usize = 1;
+    pub const PHASE: usize = 2;
+    pub const MODE: usize = 3;
+    pub const POLARITY: usize = 4;
+    pub const FADE_IN: usize = 5;
+    pub const SYNC: usize = 6;
+
     pub fn new(waveform: LfoType, frequency: f32, phase: f32) -> Lfo {
         Lfo {
             waveform,
             frequency,
             phase,
-            pos: 0.0,
+            mode: LfoMode::FreeRunning,
+            polarity: LfoPolarity::Bipolar,
+            fade_in: 0.,
+            sync: None,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            channels: 1,
+            tempo: DEFAULT_TEMPO,
+            pos: phase.rem_euclid(1.),
             last_update: 0,
+            triggered_at: 0,
+            travelled: 0.,
+            finished: false,
+            previous_random: 0.,
+            next_random: 0.,
+            random_state: RANDOM_SEED,
         }
     }
 
+    pub fn with_mode(mut self, mode: LfoMode) -> Lfo {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_polarity(mut self, polarity: LfoPolarity) -> Lfo {
+        self.polarity = polarity;
+        self
+    }
+
+    pub fn with_fade_in(mut self, seconds: f32) -> Lfo {
+        self.fade_in = seconds;
+        self
+    }
+
+    pub fn with_sync(mut self, sync: Option<NoteDivision>) -> Lfo {
+        self.sync = sync;
+        self
+    }
+
+    pub fn from_state_packet(packet: &LfoStatePacket) -> Lfo {
+        let frequency = LFO_PARAMS[Lfo::FREQUENCY].clamp(packet.frequency);
+        Lfo::new(packet.waveform, frequency, packet.phase)
+            .with_mode(packet.mode)
+            .with_polarity(packet.polarity)
+            .with_fade_in(LFO_PARAMS[Lfo::FADE_IN].clamp(packet.fade_in))
+            .with_sync(packet.sync)
+    }
+
+    pub fn prepare(&mut self, sample_rate: f32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Channels interleaved in the sample clock it's given, so it runs at the same rate
+    /// whatever it's playing in
+    pub fn set_channels(&mut self, channels: u16) {
+        self.channels = channels.max(1);
+    }
+
+    /// Starts the waveform over from its start phase
+    pub fn reset(&mut self) {
+        self.pos = self.phase.rem_euclid(1.);
+        self.travelled = 0.;
+        self.finished = false;
+    }
+
+    /// Called when a note starts, see LfoMode
+    pub fn trigger(&mut self, sample_clock: u64) {
+        self.advance(sample_clock);
+        self.triggered_at = sample_clock;
+        if self.mode != LfoMode::FreeRunning {
+            self.reset();
+        }
+
+        // Different notes get different random values
+        self.random_state ^= sample_clock as u32;
+        if self.random_state == 0 {
+            self.random_state = RANDOM_SEED;
+        }
+        self.next_random_value();
+    }
+
+    /// Tempo in BPM that synced rates follow
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo = bpm.max(1.);
+    }
+
     pub fn set_frequency(&mut self, new_frequency: f32) {
         self.frequency = new_frequency;
     }
@@ -47,34 +380,135 @@ impl Lfo {
         self.waveform = new_waveform;
     }
 
-    pub fn get_sample(&mut self, sample_clock: u64, min_value: f32, max_value: f32) -> f32 {
-        let dt = (sample_clock - self.last_update) as f32;
-        let speed = self.frequency / get_sample_rate();
-        self.pos += speed * dt;
-        self.last_update += dt as u64;
+    /// Cycles per second, following the tempo when synced
+    pub fn get_rate(&self) -> f32 {
+        match self.sync {
+            Some(division) => self.tempo / 60. / division.beats(),
+            None => self.frequency,
+        }
+    }
 
-        if self.pos > 1. {
-            self.pos -= 1.;
+    pub fn params() -> &'static [ParamInfo] {
+        &LFO_PARAMS
+    }
+
+    /// Ids are the consts on Lfo, None for unknown ones
+    pub fn get_param(&self, id: usize) -> Option<f32> {
+        match id {
+            Self::WAVEFORM => Some(self.waveform.to_param()),
+            Self::FREQUENCY => Some(self.frequency),
+            Self::PHASE => Some(self.phase),
+            Self::MODE => Some(self.mode.to_param()),
+            Self::POLARITY => Some(self.polarity.to_param()),
+            Self::FADE_IN => Some(self.fade_in),
+            Self::SYNC => Some(NoteDivision::to_param(self.sync)),
+            _ => None,
+        }
+    }
+
+    pub fn set_param(&mut self, id: usize, value: f32) {
+        match id {
+            Self::WAVEFORM => self.set_waveform(LfoType::from_param(value)),
+            Self::FREQUENCY => self.set_frequency(value),
+            Self::PHASE => self.set_phase(value),
+            Self::MODE => self.mode = LfoMode::from_param(value),
+            Self::POLARITY => self.polarity = LfoPolarity::from_param(value),
+            Self::FADE_IN => self.fade_in = value.max(0.),
+            Self::SYNC => self.sync = NoteDivision::from_param(value),
+            _ => {}
         }
+    }
+
+    // Moves the position along to sample_clock
+    fn advance(&mut self, sample_clock: u64) {
+        let dt = sample_clock.saturating_sub(self.last_update) as f32;
+        self.last_update = self.last_update.max(sample_clock);
+        if self.finished {
+            return;
+        }
+
+        let distance = self.get_rate() / self.clock_rate() * dt;
+        self.pos += distance;
+        self.travelled += distance;
 
-        let unscaled = match self.waveform {
-            LfoType::Sine => (self.pos * f32::TAU()).sin(),
-            LfoType::Saw => (self.pos * 2.) - 1.,
+        if self.mode == LfoMode::OneShot && self.travelled >= 1. {
+            self.finished = true;
+            return;
+        }
+
+        // Any number of cycles might have gone by, e.g. after a jump in the sample clock
+        if self.pos >= 1. {
+            self.pos = self.pos.fract();
+            self.next_random_value();
+        }
+    }
+
+    // Ticks of the sample clock per second
+    fn clock_rate(&self) -> f32 {
+        self.sample_rate * self.channels as f32
+    }
+
+    fn next_random_value(&mut self) {
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 17;
+        self.random_state ^= self.random_state << 5;
+
+        self.previous_random = self.next_random;
+        self.next_random = (self.random_state as f32 / u32::MAX as f32) * 2. - 1.;
+    }
+
+    fn get_current_value(&self) -> f32 {
+        // One shots stay at the very end of their cycle
+        let pos = if self.finished {
+            (self.phase + 1. - f32::EPSILON).fract()
+        } else {
+            self.pos
+        };
+
+        let bipolar = match self.waveform {
+            LfoType::Sine => (pos * f32::TAU()).sin(),
+            LfoType::Saw => (pos * 2.) - 1.,
             LfoType::Square => {
-                if self.pos < self.phase {
+                if pos < 0.5 {
                     1.
                 } else {
                     -1.
                 }
             }
+            LfoType::Triangle => 1. - 4. * (pos - 0.5).abs(),
+            LfoType::RampDown => 1. - (pos * 2.),
+            LfoType::SampleAndHold => self.next_random,
+            LfoType::SmoothRandom => {
+                // Cosine interpolation, so it doesn't kink at every new value
+                let t = (1. - (pos * f32::PI()).cos()) / 2.;
+                self.previous_random + t * (self.next_random - self.previous_random)
+            }
         };
 
-        let old_range = 2.; //Because it's -1 to 1
-        let new_range = max_value - min_value;
-        let old_value = unscaled;
-        let old_min = -1.;
-        let scaled = (((old_value - old_min) * new_range) / old_range) + min_value;
-        scaled / max_value
+        let fade = if self.fade_in > 0. {
+            let elapsed = self.last_update.saturating_sub(self.triggered_at) as f32;
+            (elapsed / (self.fade_in * self.clock_rate())).min(1.)
+        } else {
+            1.
+        };
+
+        match self.polarity {
+            LfoPolarity::Bipolar => bipolar * fade,
+            LfoPolarity::Unipolar => (bipolar + 1.) / 2. * fade,
+        }
+    }
+
+    /// Advances to sample_clock and returns the output there, -1 to 1 or 0 to 1 when
+    /// unipolar. Turn it into a parameter value with one of the map functions
+    pub fn get_value(&mut self, sample_clock: u64) -> f32 {
+        self.advance(sample_clock);
+        self.get_current_value()
+    }
+
+    /// The output at sample_clock mapped from -1..1 onto min_value..max_value, unipolar
+    /// LFOs only reach the upper half
+    pub fn get_sample(&mut self, sample_clock: u64, min_value: f32, max_value: f32) -> f32 {
+        map_range(self.get_value(sample_clock), min_value, max_value)
     }
 
     pub fn get_state_packet(&self) -> LfoStatePacket {
@@ -82,6 +516,127 @@ impl Lfo {
             waveform: self.waveform,
             frequency: self.frequency,
             phase: self.phase,
+            mode: self.mode,
+            polarity: self.polarity,
+            fade_in: self.fade_in,
+            sync: self.sync,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.;
+
+    // Cycles a saw runs through over a second of a sample clock that counts every channel
+    fn cycles_per_second(lfo: &mut Lfo, channels: u16) -> usize {
+        lfo.prepare(SAMPLE_RATE, 0);
+        lfo.set_channels(channels);
+
+        let ticks = SAMPLE_RATE as u64 * channels as u64;
+        let values: Vec<f32> = (0..ticks).map(|t| lfo.get_value(t)).collect();
+        values.windows(2).filter(|w| w[1] < w[0]).count()
+    }
+
+    #[test]
+    fn quarter_notes_at_120_bpm_run_at_2_hz() {
+        // Off the cycle's start, so no wrap lands right on the end of the second
+        let mut lfo = Lfo::new(LfoType::Saw, 0., 0.25).with_sync(Some(NoteDivision::Quarter));
+        lfo.set_tempo(120.);
+        assert_eq!(lfo.get_rate(), 2.);
+
+        assert_eq!(cycles_per_second(&mut lfo.clone(), 1), 2);
+        assert_eq!(cycles_per_second(&mut lfo, 2), 2);
+    }
+
+    #[test]
+    fn fade_in_takes_as_long_in_stereo() {
+        let mut lfo = Lfo::new(LfoType::Square, 1., 0.).with_fade_in(1.);
+        lfo.prepare(SAMPLE_RATE, 0);
+        lfo.set_channels(2);
+        lfo.trigger(0);
+
+        // A quarter of a second in, counting both channels
+        let value = lfo.get_value(SAMPLE_RATE as u64 / 2);
+        assert!((value - 0.25).abs() < 1e-3, "faded in to {}", value);
+    }
+
+    const TYPES: [LfoType; 7] = [
+        LfoType::Sine,
+        LfoType::Saw,
+        LfoType::Square,
+        LfoType::Triangle,
+        LfoType::RampDown,
+        LfoType::SampleAndHold,
+        LfoType::SmoothRandom,
+    ];
+
+    #[test]
+    fn stays_in_the_cycle_after_a_jump_in_the_clock() {
+        let mut lfo = Lfo::new(LfoType::Saw, 3., 0.);
+        lfo.prepare(SAMPLE_RATE, 0);
+
+        // 1000.1 seconds at 3 Hz, 3000.3 cycles in
+        let value = lfo.get_value(48_004_800);
+        assert!((0. ..1.).contains(&lfo.pos), "position is {}", lfo.pos);
+        assert!((value - -0.4).abs() < 1e-2, "saw is at {}", value);
+    }
+
+    #[test]
+    fn maps_onto_ranges() {
+        assert_eq!(map_range(-1., 100., 200.), 100.);
+        assert_eq!(map_range(0., 100., 200.), 150.);
+        assert_eq!(map_range(1., 100., 200.), 200.);
+
+        assert_eq!(map_linear(-1., 0.5, 0.25), 0.25);
+        assert_eq!(map_linear(0., 0.5, 0.25), 0.5);
+        assert_eq!(map_linear(1., 0.5, 0.25), 0.75);
+
+        assert_eq!(map_octaves(-1., 1000., 2.), 250.);
+        assert_eq!(map_octaves(0., 1000., 2.), 1000.);
+        assert_eq!(map_octaves(1., 1000., 2.), 4000.);
+    }
+
+    #[test]
+    fn every_type_stays_in_bounds() {
+        for &waveform in TYPES.iter() {
+            for &(polarity, min) in
+                [(LfoPolarity::Bipolar, -1.), (LfoPolarity::Unipolar, 0.)].iter()
+            {
+                let mut lfo = Lfo::new(waveform, 7., 0.3).with_polarity(polarity);
+                lfo.prepare(SAMPLE_RATE, 0);
+                lfo.trigger(0);
+
+                for t in 0..SAMPLE_RATE as u64 {
+                    let value = lfo.get_value(t);
+                    assert!(
+                        (min..=1.).contains(&value),
+                        "{:?} {:?} is at {}",
+                        waveform,
+                        polarity,
+                        value
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn one_shots_stay_at_the_end_of_their_cycle() {
+        let mut lfo = Lfo::new(LfoType::Saw, 2., 0.).with_mode(LfoMode::OneShot);
+        lfo.prepare(SAMPLE_RATE, 0);
+        lfo.trigger(0);
+
+        // Well past the single cycle, which takes half a second
+        for t in [SAMPLE_RATE as u64, 10 * SAMPLE_RATE as u64].iter() {
+            let value = lfo.get_value(*t);
+            assert!((value - 1.).abs() < 1e-3, "saw stopped at {}", value);
+        }
+
+        // Until the next note
+        lfo.trigger(11 * SAMPLE_RATE as u64);
+        assert!((lfo.get_value(11 * SAMPLE_RATE as u64) - -1.).abs() < 1e-3);
+    }
+}
@@ -1,35 +1,137 @@
-use crate::filters::{
-    IIRFilterStatePacket, IIRLowPassFilter, ModulatedFilter, ModulatedFilterStatePacket,
-    StateVariableTPTFilter, StateVariableTPTFilterStatePacket,
-};
-use cpal::SampleRate;
-use once_cell::sync::OnceCell;
+use crate::filters::{IIRLowPassFilter, ModulatedFilter, StateVariableTPTFilter};
+use serde::{Deserialize, Serialize};
 
 pub mod adsr;
 pub mod filters;
 pub mod lfo;
+mod processor;
+pub mod smoothing;
 
-// TODO: rather janky solution to avoid cyclic dependency with ravetable bin crate
-//       rework a better option in the future.
-pub static SAMPLE_RATE: OnceCell<SampleRate> = OnceCell::new();
+pub use processor::{ParamInfo, Processor, Unit};
 
-pub fn get_sample_rate() -> f32 {
-    SAMPLE_RATE.get().unwrap().0 as f32
-}
+/// Sample rate processors run at until they're prepared for another one
+pub const DEFAULT_SAMPLE_RATE: f32 = 48000.;
+
+// Declares the Effect enum and its matching state packet, dispatching Processor to the
+// variants. Adding an effect only takes a line in the invocation below
+macro_rules! effects {
+    ($($variant:ident($effect:ty)),* $(,)?) => {
+        #[derive(Clone)]
+        pub enum Effect {
+            $($variant($effect),)*
+        }
+
+        #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+        pub enum EffectStatePacket {
+            $($variant(<$effect as Processor>::StatePacket),)*
+        }
+
+        impl Processor for Effect {
+            type StatePacket = EffectStatePacket;
+
+            fn from_state_packet(packet: &EffectStatePacket, sample_rate: f32) -> Effect {
+                match packet {
+                    $(EffectStatePacket::$variant(p) => {
+                        Effect::$variant(<$effect>::from_state_packet(p, sample_rate))
+                    })*
+                }
+            }
+
+            fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+                match self {
+                    $(Effect::$variant(e) => e.prepare(sample_rate, max_block),)*
+                }
+            }
+
+            fn reset(&mut self) {
+                match self {
+                    $(Effect::$variant(e) => e.reset(),)*
+                }
+            }
+
+            fn set_smoothing_time(&mut self, seconds: f32) {
+                match self {
+                    $(Effect::$variant(e) => e.set_smoothing_time(seconds),)*
+                }
+            }
+
+            fn trigger(&mut self, sample_clock: u64) {
+                match self {
+                    $(Effect::$variant(e) => e.trigger(sample_clock),)*
+                }
+            }
+
+            fn set_tempo(&mut self, bpm: f32) {
+                match self {
+                    $(Effect::$variant(e) => e.set_tempo(bpm),)*
+                }
+            }
+
+            fn set_channels(&mut self, channels: u16) {
+                match self {
+                    $(Effect::$variant(e) => e.set_channels(channels),)*
+                }
+            }
 
-pub fn set_effects_sample_rate(sample_rate: SampleRate) {
-    SAMPLE_RATE.set(sample_rate).unwrap();
+            fn process_samples(&mut self, sample_clock: u64, samples: &mut [f32]) {
+                match self {
+                    $(Effect::$variant(e) => e.process_samples(sample_clock, samples),)*
+                }
+            }
+
+            fn params(&self) -> &'static [ParamInfo] {
+                match self {
+                    $(Effect::$variant(e) => e.params(),)*
+                }
+            }
+
+            fn get_param(&self, id: usize) -> Option<f32> {
+                match self {
+                    $(Effect::$variant(e) => e.get_param(id),)*
+                }
+            }
+
+            fn set_param(&mut self, id: usize, value: f32) {
+                match self {
+                    $(Effect::$variant(e) => e.set_param(id, value),)*
+                }
+            }
+
+            fn set_param_immediate(&mut self, id: usize, value: f32) {
+                match self {
+                    $(Effect::$variant(e) => e.set_param_immediate(id, value),)*
+                }
+            }
+
+            fn get_state_packet(&self) -> EffectStatePacket {
+                match self {
+                    $(Effect::$variant(e) => EffectStatePacket::$variant(e.get_state_packet()),)*
+                }
+            }
+        }
+
+        // Lets copies of the state (e.g. the GUI's) be addressed by the same parameter ids.
+        // Goes through a temporary processor, so it allocates
+        impl EffectStatePacket {
+            pub fn params(&self) -> &'static [ParamInfo] {
+                Effect::from_state_packet(self, DEFAULT_SAMPLE_RATE).params()
+            }
+
+            pub fn get_param(&self, id: usize) -> Option<f32> {
+                Effect::from_state_packet(self, DEFAULT_SAMPLE_RATE).get_param(id)
+            }
+
+            pub fn set_param(&mut self, id: usize, value: f32) {
+                let mut effect = Effect::from_state_packet(self, DEFAULT_SAMPLE_RATE);
+                effect.set_param(id, value);
+                *self = effect.get_state_packet();
+            }
+        }
+    };
 }
 
-pub enum Effect {
+effects! {
     ModulatedFilter(ModulatedFilter),
     IIRFilter(IIRLowPassFilter), // TODO: IIRFilter should be more than lowpass
     StateVariablePTPFilter(StateVariableTPTFilter),
 }
-
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum EffectStatePacket {
-    ModulatedFilter(ModulatedFilterStatePacket),
-    IIRFilter(IIRFilterStatePacket),
-    StateVariablePTPFilter(StateVariableTPTFilterStatePacket),
-}
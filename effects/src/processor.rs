@@ -0,0 +1,130 @@
+//! The interface every effect implements, so oscillators and the GUI can treat them alike
+
+/// What a parameter's value means, mostly for displaying it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Unit {
+    None,
+    Hertz,
+    Seconds,
+    Semitones,
+    Cents,
+    /// Stepped parameter, the value is an index into these names
+    Choice(&'static [&'static str]),
+}
+
+/// Describes one of a processor's parameters. Parameters are addressed by their
+/// index into `Processor::params`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub unit: Unit,
+    // Below 1 gives the low end of the range more room on a control, above 1 the high end
+    pub skew: f32,
+}
+
+impl ParamInfo {
+    pub const fn new(name: &'static str, min: f32, max: f32, default: f32) -> ParamInfo {
+        ParamInfo {
+            name,
+            min,
+            max,
+            default,
+            unit: Unit::None,
+            skew: 1.,
+        }
+    }
+
+    pub const fn with_unit(mut self, unit: Unit) -> ParamInfo {
+        self.unit = unit;
+        self
+    }
+
+    pub const fn with_skew(mut self, skew: f32) -> ParamInfo {
+        self.skew = skew;
+        self
+    }
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.clamp(self.min, self.max)
+    }
+
+    /// 0 to 1 across the range, with the skew applied
+    pub fn to_normalized(&self, value: f32) -> f32 {
+        if self.max <= self.min {
+            return 0.;
+        }
+        ((self.clamp(value) - self.min) / (self.max - self.min)).powf(self.skew)
+    }
+
+    pub fn from_normalized(&self, normalized: f32) -> f32 {
+        let proportion = normalized.clamp(0., 1.).powf(1. / self.skew);
+        self.min + (self.max - self.min) * proportion
+    }
+
+    pub fn format(&self, value: f32) -> String {
+        match self.unit {
+            Unit::None => format!("{:.2}", value),
+            Unit::Hertz if value >= 1000. => format!("{:.2} kHz", value / 1000.),
+            Unit::Hertz => format!("{:.1} Hz", value),
+            Unit::Seconds if value < 1. => format!("{:.0} ms", value * 1000.),
+            Unit::Seconds => format!("{:.2} s", value),
+            Unit::Semitones => format!("{:+.0} st", value),
+            Unit::Cents => format!("{:+.0} ct", value),
+            Unit::Choice(names) => {
+                let index = (value.round().max(0.) as usize).min(names.len().saturating_sub(1));
+                names.get(index).unwrap_or(&"").to_string()
+            }
+        }
+    }
+}
+
+pub trait Processor {
+    /// Everything needed to recreate the processor, minus its running state
+    type StatePacket;
+
+    fn from_state_packet(packet: &Self::StatePacket, sample_rate: f32) -> Self
+    where
+        Self: Sized;
+
+    /// Has to be called whenever the sample rate changes, before processing any samples.
+    /// max_block is the most samples that will be processed at once
+    fn prepare(&mut self, sample_rate: f32, max_block: usize);
+
+    /// Clears the running state (e.g. filter memory) without touching the parameters
+    fn reset(&mut self);
+
+    /// How long parameter changes take to ramp to their new values, for processors
+    /// that smooth them
+    fn set_smoothing_time(&mut self, _seconds: f32) {}
+
+    /// Called whenever a note starts, for processors with per-note state like LFOs
+    fn trigger(&mut self, _sample_clock: u64) {}
+
+    /// Tempo in BPM, for processors that sync to it
+    fn set_tempo(&mut self, _bpm: f32) {}
+
+    /// Channels interleaved in the sample clock, for processors that keep time by it
+    fn set_channels(&mut self, _channels: u16) {}
+
+    /// Processes samples in place, sample_clock being the time of the first one
+    fn process_samples(&mut self, sample_clock: u64, samples: &mut [f32]);
+
+    fn params(&self) -> &'static [ParamInfo];
+
+    /// None if there's no parameter with this id
+    fn get_param(&self, id: usize) -> Option<f32>;
+
+    /// Unknown ids are ignored
+    fn set_param(&mut self, id: usize, value: f32);
+
+    /// Same as set_param, but smoothed parameters jump to the value instead of ramping to it.
+    /// For values that already move in small steps, like modulation
+    fn set_param_immediate(&mut self, id: usize, value: f32) {
+        self.set_param(id, value);
+    }
+
+    fn get_state_packet(&self) -> Self::StatePacket;
+}
@@ -0,0 +1,123 @@
+//! Ramps parameters towards new values over a short time instead of jumping, which
+//! would be heard as zipper noise while turning a knob
+
+use crate::DEFAULT_SAMPLE_RATE;
+
+/// Seconds a smoothed value takes to reach a new target unless told otherwise
+pub const DEFAULT_SMOOTHING_TIME: f32 = 0.02;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Smoothing {
+    /// Equal steps, for levels like gain
+    Linear,
+    /// Equal ratios, for values heard logarithmically like frequencies. Ramps from or to
+    /// 0 and below are linear, since there's no ratio to step by
+    Exponential,
+}
+
+#[derive(Clone, Debug)]
+pub struct SmoothedValue {
+    smoothing: Smoothing,
+    current: f32,
+    target: f32,
+
+    // Added to or multiplied with current every sample, depending on multiply
+    step: f32,
+    multiply: bool,
+    remaining: u32,
+
+    sample_rate: f32,
+    // Seconds, ramp_length is the same in samples
+    ramp_time: f32,
+    ramp_length: u32,
+}
+
+impl SmoothedValue {
+    pub fn new(value: f32, smoothing: Smoothing) -> SmoothedValue {
+        let mut smoothed = SmoothedValue {
+            smoothing,
+            current: value,
+            target: value,
+            step: 0.,
+            multiply: false,
+            remaining: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            ramp_time: DEFAULT_SMOOTHING_TIME,
+            ramp_length: 0,
+        };
+        smoothed.prepare(DEFAULT_SAMPLE_RATE);
+        smoothed
+    }
+
+    /// Has to be called whenever the sample rate changes, a ramp that's still going is
+    /// cut short
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.ramp_length = (self.ramp_time * sample_rate) as u32;
+        self.set_immediate(self.target);
+    }
+
+    /// 0 makes every change take effect right away
+    pub fn set_ramp_time(&mut self, seconds: f32) {
+        self.ramp_time = seconds.max(0.);
+        self.ramp_length = (self.ramp_time * self.sample_rate) as u32;
+    }
+
+    /// Starts ramping from wherever the value is now
+    pub fn set_target(&mut self, target: f32) {
+        if target == self.target {
+            return;
+        }
+
+        self.target = target;
+        if self.ramp_length == 0 {
+            self.set_immediate(target);
+            return;
+        }
+
+        self.remaining = self.ramp_length;
+        self.multiply =
+            self.smoothing == Smoothing::Exponential && self.current > 0. && target > 0.;
+        self.step = if self.multiply {
+            (target / self.current).powf(1. / self.ramp_length as f32)
+        } else {
+            (target - self.current) / self.ramp_length as f32
+        };
+    }
+
+    /// Jumps straight to the value, e.g. when nothing is playing yet
+    pub fn set_immediate(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.remaining = 0;
+    }
+
+    /// Moves one sample further along the ramp and returns the new value
+    #[inline(always)]
+    pub fn get_next_value(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                // No rounding errors left over at the end of the ramp
+                self.current = self.target;
+            } else if self.multiply {
+                self.current *= self.step;
+            } else {
+                self.current += self.step;
+            }
+        }
+        self.current
+    }
+
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    pub fn get_current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn get_target(&self) -> f32 {
+        self.target
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// What the audio side needs to know about the stream it renders into.
+/// Owned by the mixer and handed down to whatever needs it, so the audio
+/// thread never has to take a lock to find out the time or the sample rate.
+#[derive(Clone, Debug)]
+pub struct EngineContext {
+    pub sample_rate: f32,
+    // Samples of every channel rendered so far
+    pub sample_clock: u64,
+    // Samples (not frames) rendered at a time
+    pub block_size: u32,
+}
+
+/// Small enough for knobs and notes to react quickly, big enough to keep
+/// the per-block overhead down
+pub const DEFAULT_BLOCK_SIZE: u32 = 128;
+
+impl EngineContext {
+    pub fn new(sample_rate: u32) -> EngineContext {
+        EngineContext {
+            sample_rate: sample_rate as f32,
+            sample_clock: 0,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    pub fn with_block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    pub fn advance_sample_clock(&mut self, sample_count: u64) {
+        self.sample_clock += sample_count;
+    }
+}
+
+/// The mixer's sample clock as seen from other threads, for timing messages with
+/// `Message::Timed`. It moves on once per rendered block, so it's where the next block starts
+#[derive(Clone, Debug, Default)]
+pub struct SampleClock(Arc<AtomicU64>);
+
+impl SampleClock {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set(&self, sample_clock: u64) {
+        self.0.store(sample_clock, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EngineError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to decode {path}: {source}")]
+    InvalidWav { path: String, source: hound::Error },
+    #[error("{0} doesn't contain any samples")]
+    EmptyWavetable(String),
+    #[error("No wavetables found in {0}")]
+    NoWavetables(String),
+    #[error("No wavetables to build the patch with")]
+    NoSamples,
+    #[error("A patch needs at least one voice")]
+    NoVoices,
+    #[error("{path} isn't a valid preset: {source}")]
+    InvalidPreset {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[error("Preset has no oscillators")]
+    EmptyPreset,
+}
@@ -0,0 +1,21 @@
+//! Everything in Ravetable that makes sound, without the GUI or an audio device.
+//!
+//! `Synth` is the simple way in: feed it notes and parameter changes, and have it fill
+//! buffers with interleaved stereo. The modules underneath are public too, for frontends
+//! that need more control (background wavetable loading, presets, sequences).
+
+pub use crate::error::EngineError;
+pub use crate::synth::Synth;
+
+pub mod context;
+mod error;
+pub mod loader;
+pub mod messages;
+pub mod mixer;
+pub mod modulation;
+pub mod params;
+pub mod patch;
+pub mod preset;
+mod synth;
+pub mod synths;
+pub mod tuning;
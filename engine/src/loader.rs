@@ -0,0 +1,129 @@
+//! Wavetable loading in the background
+//!
+//! Decoding a wav file and building its mipmaps takes far longer than an audio callback is
+//! allowed to, so the mixer only queues up requests. A loader thread does the actual work and
+//! hands finished tables back through a channel that the mixer polls once per chunk.
+//!
+//! The loader also keeps a reference to every table it knows about. Once the oscillators have
+//! all moved on to a newer table, the loader's reference is the last one left and the table gets
+//! freed here instead of on the audio thread. Messages the mixer is done with that still own
+//! memory (boxed timed messages, requests the loader had no room for) are freed here too.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::{select, Receiver, Sender};
+use log::{error, info};
+
+use crate::messages::Message;
+use crate::synths::{Sample, Wavetable};
+
+// How often unused tables get cleaned up when no requests are coming in
+const CLEANUP_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The mixer's end of the loader
+pub struct WavetableLoader {
+    requests: Sender<(usize, Sample)>,
+    loaded: Receiver<(usize, Arc<Wavetable>)>,
+    retired: Sender<Message>,
+}
+
+impl WavetableLoader {
+    /// Never blocks, the request is handed back when the loader is too far behind
+    pub fn request(&self, osc_id: usize, sample: Sample) -> Result<(), (usize, Sample)> {
+        self.requests
+            .try_send((osc_id, sample))
+            .map_err(|e| e.into_inner())
+    }
+
+    /// Frees the message on the loader thread. Never blocks, the message is handed back when
+    /// the loader is too far behind
+    pub fn retire(&self, message: Message) -> Result<(), Message> {
+        self.retired.try_send(message).map_err(|e| e.into_inner())
+    }
+
+    pub fn try_receive(&self) -> Option<(usize, Arc<Wavetable>)> {
+        self.loaded.try_recv().ok()
+    }
+}
+
+/// Starts the loader thread. current has the table every oscillator starts out with,
+/// load errors are sent to the GUI through event_sender.
+pub fn start_wavetable_loader(
+    current: Vec<Arc<Wavetable>>,
+    event_sender: Sender<Message>,
+) -> WavetableLoader {
+    let (request_sender, request_receiver) = crossbeam_channel::bounded(16);
+    let (loaded_sender, loaded_receiver) = crossbeam_channel::bounded(16);
+    let (retired_sender, retired_receiver) = crossbeam_channel::bounded(MAX_RETIRED_MESSAGES);
+
+    thread::spawn(move || {
+        run_loader(
+            current,
+            request_receiver,
+            loaded_sender,
+            retired_receiver,
+            event_sender,
+        );
+    });
+
+    WavetableLoader {
+        requests: request_sender,
+        loaded: loaded_receiver,
+        retired: retired_sender,
+    }
+}
+
+// Messages waiting to be freed, the mixer holds on to any more until there is room
+const MAX_RETIRED_MESSAGES: usize = 256;
+
+fn run_loader(
+    mut current: Vec<Arc<Wavetable>>,
+    requests: Receiver<(usize, Sample)>,
+    loaded: Sender<(usize, Arc<Wavetable>)>,
+    retired_messages: Receiver<Message>,
+    event_sender: Sender<Message>,
+) {
+    // Replaced tables, some oscillators might still be playing them
+    let mut retired: Vec<Arc<Wavetable>> = vec![];
+
+    loop {
+        select! {
+            recv(requests) -> request => match request {
+                // The mixer only asks for oscillators it has, but a table that can't be
+                // stored would never get freed
+                Ok((osc_id, _)) if osc_id >= current.len() => {}
+                Ok((osc_id, sample)) => match Wavetable::create_wavetable(sample) {
+                    Ok(wavetable) => {
+                        info!("Loaded wavetable {}", wavetable.sample.name);
+
+                        let wavetable = Arc::new(wavetable);
+                        retired.push(std::mem::replace(&mut current[osc_id], wavetable.clone()));
+                        if loaded.send((osc_id, wavetable)).is_err() {
+                            // Mixer is gone
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to load wavetable: {}", e);
+                        let playing = current[osc_id].sample.clone();
+                        let _ = event_sender
+                            .send(Message::WavetableError(osc_id, playing, e.to_string()));
+                    }
+                },
+                Err(_) => break,
+            },
+            // Dropping them is all there's left to do
+            recv(retired_messages) -> message => {
+                if let Ok(Message::SampleChange(_, sample)) = message {
+                    error!("Wavetable loader is busy, ignoring the change to {}", sample.name);
+                }
+            },
+            default(CLEANUP_INTERVAL) => {}
+        }
+
+        // Only the loader's own reference left means nothing is playing it anymore
+        retired.retain(|wavetable| Arc::strong_count(wavetable) > 1);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::modulation::ModRouteChange;
+use crate::params::ParamId;
+use crate::synths::Sample;
+
+/// Controller number of the modulation wheel
+pub const MOD_WHEEL_CC: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    // note, velocity
+    NoteOn(u8, f32),
+    NoteOff(u8),
+    // -1 to 1, scaled by the mixer's pitch bend range
+    PitchBend(f32),
+    // Frequency of A4 in Hz
+    MasterTune(f32),
+    // Beats per minute that tempo synced LFOs follow
+    Tempo(f32),
+    ModWheel(f32),
+    // Channel pressure, 0 to 1
+    Aftertouch(f32),
+    // controller, 0 to 1
+    ControlChange(u8, f32),
+
+    // Values outside of the parameter's range are clamped
+    SetParam(ParamId, f32),
+    // Adds, changes or removes routes of the modulation matrix
+    ModRoute(ModRouteChange),
+    // osc_id, wavetable to switch to
+    SampleChange(usize, Sample),
+
+    // Handles the message at the given sample clock time instead of at the start of
+    // the next chunk, late ones are handled right away. Other threads can read the
+    // clock through Mixer::get_sample_clock
+    Timed(u64, Box<Message>),
+
+    // Sent back to the GUI from the audio side
+    // osc_id, sample that's still playing, what went wrong
+    WavetableError(usize, Sample, String),
+    Error(String),
+}
@@ -0,0 +1,1039 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use log::error;
+
+use crate::context::{EngineContext, SampleClock};
+use crate::loader::WavetableLoader;
+use crate::messages::{Message, MOD_WHEEL_CC};
+use crate::modulation::{
+    next_random, GlobalSources, ModMatrix, ModRouteChange, ModSource, ModulationStatePacket,
+    MOD_LFO_COUNT,
+};
+use crate::params::{ParamId, ENVELOPE_PARAMS, LFO_PARAMS, OSC_PARAMS};
+use crate::synths::OscStatePacket;
+use crate::synths::{Oscillator, Wavetable};
+use crate::tuning::{midi_note_to_frequency, DEFAULT_MASTER_TUNE};
+use effects::adsr::ADSREnvelope;
+use effects::lfo::{Lfo, DEFAULT_TEMPO};
+use effects::{ParamInfo, Processor};
+
+#[derive(Clone)]
+pub struct MixerStatePacket {
+    pub oscillators: Vec<OscStatePacket>,
+    // Parameters hold their unmodulated values, routes are added on top while playing
+    pub modulation: ModulationStatePacket,
+}
+
+impl MixerStatePacket {
+    /// Keeps a copy of the state in sync with the parameter messages sent to the mixer
+    pub fn apply_message(&mut self, message: &Message) {
+        match message {
+            Message::SetParam(id, value) => {
+                if let Some(info) = self.param_info(*id) {
+                    self.set_param(*id, info.clamp(*value));
+                }
+            }
+            Message::SampleChange(id, sample) | Message::WavetableError(id, sample, _) => {
+                if let Some(osc) = self.oscillators.get_mut(*id) {
+                    osc.name = sample.name.clone();
+                }
+            }
+            Message::ModRoute(change) => change.apply(&mut self.modulation.routes),
+            Message::Timed(_, message) => self.apply_message(message),
+            _ => {}
+        }
+    }
+
+    /// Every parameter of every oscillator, effect and modulation source, in a stable order
+    pub fn params(&self) -> Vec<(ParamId, ParamInfo)> {
+        let mut params = vec![];
+        for (osc_id, osc) in self.oscillators.iter().enumerate() {
+            params.extend(
+                OSC_PARAMS
+                    .iter()
+                    .map(|p| (ParamId::Osc(osc_id, *p), *p.info())),
+            );
+            params.extend(
+                ENVELOPE_PARAMS
+                    .iter()
+                    .map(|p| (ParamId::Envelope(osc_id, *p), *p.info())),
+            );
+            for (effect_id, effect) in osc.effects.iter().enumerate() {
+                params.extend(
+                    effect
+                        .params()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, info)| (ParamId::Effect(osc_id, effect_id, i), *info)),
+                );
+            }
+        }
+        for lfo_id in 0..self.modulation.lfos.len() {
+            params.extend(
+                LFO_PARAMS
+                    .iter()
+                    .map(|p| (ParamId::ModLfo(lfo_id, *p), *p.info())),
+            );
+        }
+        for envelope_id in 0..self.modulation.envelopes.len() {
+            params.extend(
+                ENVELOPE_PARAMS
+                    .iter()
+                    .map(|p| (ParamId::ModEnvelope(envelope_id, *p), *p.info())),
+            );
+        }
+        params
+    }
+
+    /// None if there's no such parameter in this patch
+    pub fn param_info(&self, id: ParamId) -> Option<ParamInfo> {
+        match id {
+            ParamId::Osc(osc_id, param) => self.oscillators.get(osc_id).map(|_| *param.info()),
+            ParamId::Envelope(osc_id, param) => self.oscillators.get(osc_id).map(|_| *param.info()),
+            ParamId::Effect(osc_id, effect_id, param) => self
+                .oscillators
+                .get(osc_id)?
+                .effects
+                .get(effect_id)?
+                .params()
+                .get(param)
+                .copied(),
+            ParamId::ModLfo(lfo_id, param) => {
+                self.modulation.lfos.get(lfo_id).map(|_| *param.info())
+            }
+            ParamId::ModEnvelope(envelope_id, param) => self
+                .modulation
+                .envelopes
+                .get(envelope_id)
+                .map(|_| *param.info()),
+        }
+    }
+
+    pub fn get_param(&self, id: ParamId) -> Option<f32> {
+        match id {
+            ParamId::Osc(osc_id, param) => self.oscillators.get(osc_id).map(|o| o.get_param(param)),
+            ParamId::Envelope(osc_id, param) => {
+                self.oscillators.get(osc_id).map(|o| param.get(&o.adsr))
+            }
+            ParamId::Effect(osc_id, effect_id, param) => self
+                .oscillators
+                .get(osc_id)?
+                .effects
+                .get(effect_id)?
+                .get_param(param),
+            ParamId::ModLfo(lfo_id, param) => self
+                .modulation
+                .lfos
+                .get(lfo_id)
+                .map(|l| param.get(&Lfo::from_state_packet(l))),
+            ParamId::ModEnvelope(envelope_id, param) => self
+                .modulation
+                .envelopes
+                .get(envelope_id)
+                .map(|e| param.get(e)),
+        }
+    }
+
+    /// Unknown parameters are ignored
+    pub fn set_param(&mut self, id: ParamId, value: f32) {
+        match id {
+            ParamId::Osc(osc_id, param) => {
+                if let Some(osc) = self.oscillators.get_mut(osc_id) {
+                    osc.set_param(param, value);
+                }
+            }
+            ParamId::Envelope(osc_id, param) => {
+                if let Some(osc) = self.oscillators.get_mut(osc_id) {
+                    param.set(&mut osc.adsr, value);
+                }
+            }
+            ParamId::Effect(osc_id, effect_id, param) => {
+                if let Some(effect) = self
+                    .oscillators
+                    .get_mut(osc_id)
+                    .and_then(|o| o.effects.get_mut(effect_id))
+                {
+                    effect.set_param(param, value);
+                }
+            }
+            ParamId::ModLfo(lfo_id, param) => {
+                if let Some(packet) = self.modulation.lfos.get_mut(lfo_id) {
+                    let mut lfo = Lfo::from_state_packet(packet);
+                    param.set(&mut lfo, value);
+                    *packet = lfo.get_state_packet();
+                }
+            }
+            ParamId::ModEnvelope(envelope_id, param) => {
+                if let Some(envelope) = self.modulation.envelopes.get_mut(envelope_id) {
+                    param.set(envelope, value);
+                }
+            }
+        }
+    }
+}
+
+/// Which voice gets cut off when a note comes in and every voice is busy
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VoiceStealing {
+    Oldest,
+    Quietest,
+}
+
+// Room for timed messages before scheduling one has to allocate
+const MAX_SCHEDULED_MESSAGES: usize = 256;
+
+// Room for messages waiting on the wavetable loader to free them, past that they get freed
+// on the audio thread
+const MAX_RETIRING_MESSAGES: usize = 64;
+
+/// Semitones up/down at full pitch wheel deflection
+const PITCH_BEND_RANGE: f32 = 2.;
+
+/// A single playable note, owning its own copy of every oscillator so that phases,
+/// envelopes and filter states don't interfere between notes
+struct Voice {
+    oscillators: Vec<Oscillator>,
+    // Envelopes of the modulation matrix, they don't affect the level unless routed to it
+    mod_envelopes: Vec<ADSREnvelope>,
+    // LFOs of the modulation matrix and their values at the start of the current chunk
+    mod_lfos: Vec<Lfo>,
+    lfo_values: [f32; MOD_LFO_COUNT],
+    // Scratch space for one oscillator's chunk at a time, as long as the mixer's blocks
+    buffer: Vec<f32>,
+    note: Option<u8>,
+    // Last note played, unlike note it's kept while the note is released
+    key: u8,
+    velocity: f32,
+    frequency: f32,
+    // Random modulation source, picked on every note
+    random: f32,
+    started_at: u64,
+}
+
+impl Voice {
+    fn new(oscillators: Vec<Oscillator>, modulation: &ModMatrix, block_size: usize) -> Voice {
+        Voice {
+            oscillators,
+            mod_envelopes: modulation
+                .envelopes
+                .iter()
+                .map(|adsr| ADSREnvelope::new(*adsr))
+                .collect(),
+            mod_lfos: modulation.lfos.clone(),
+            lfo_values: [0.; MOD_LFO_COUNT],
+            buffer: vec![0.; block_size],
+            note: None,
+            key: 0,
+            velocity: 0.,
+            frequency: 0.,
+            random: 0.,
+            started_at: 0,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.oscillators.iter().any(|o| o.envelope.is_active())
+    }
+
+    fn get_level(&self) -> f32 {
+        self.oscillators
+            .iter()
+            .map(|o| o.envelope.get_last_value())
+            .sum()
+    }
+
+    fn note_on(
+        &mut self,
+        note: u8,
+        velocity: f32,
+        sample_clock: u64,
+        pitch_bend: f32,
+        master_tune: f32,
+        random: f32,
+    ) {
+        self.note = Some(note);
+        self.key = note;
+        self.velocity = velocity;
+        self.frequency = midi_note_to_frequency(note, master_tune);
+        self.random = random;
+        self.started_at = sample_clock;
+
+        self.set_pitch_bend(pitch_bend);
+        self.oscillators
+            .iter_mut()
+            .for_each(|o| o.trigger(sample_clock, velocity));
+        self.mod_envelopes
+            .iter_mut()
+            .for_each(|e| e.trigger(sample_clock, velocity));
+        self.mod_lfos
+            .iter_mut()
+            .for_each(|l| l.trigger(sample_clock));
+    }
+
+    // pitch_bend is a frequency ratio
+    fn set_pitch_bend(&mut self, pitch_bend: f32) {
+        let frequency = self.frequency * pitch_bend;
+        self.oscillators
+            .iter_mut()
+            .for_each(|o| o.set_frequency(frequency));
+    }
+
+    fn note_off(&mut self, sample_clock: u64) {
+        self.note = None;
+        self.oscillators
+            .iter_mut()
+            .for_each(|o| o.release(sample_clock));
+        self.mod_envelopes
+            .iter_mut()
+            .for_each(|e| e.release(sample_clock));
+    }
+
+    fn param_info(&self, id: ParamId) -> Option<ParamInfo> {
+        match id {
+            ParamId::Osc(osc_id, param) => self.oscillators.get(osc_id).map(|_| *param.info()),
+            ParamId::Envelope(osc_id, param) => self.oscillators.get(osc_id).map(|_| *param.info()),
+            ParamId::Effect(osc_id, effect_id, param) => self
+                .oscillators
+                .get(osc_id)?
+                .effects
+                .get(effect_id)?
+                .params()
+                .get(param)
+                .copied(),
+            ParamId::ModEnvelope(envelope_id, param) => {
+                self.mod_envelopes.get(envelope_id).map(|_| *param.info())
+            }
+            // Shared by all voices, the mixer has them
+            ParamId::ModLfo(..) => None,
+        }
+    }
+
+    fn get_param(&self, id: ParamId) -> Option<f32> {
+        match id {
+            ParamId::Osc(osc_id, param) => self.oscillators.get(osc_id).map(|o| o.get_param(param)),
+            ParamId::Envelope(osc_id, param) => self
+                .oscillators
+                .get(osc_id)
+                .map(|o| param.get(&o.envelope.adsr_values)),
+            ParamId::Effect(osc_id, effect_id, param) => self
+                .oscillators
+                .get(osc_id)?
+                .effects
+                .get(effect_id)?
+                .get_param(param),
+            ParamId::ModEnvelope(envelope_id, param) => self
+                .mod_envelopes
+                .get(envelope_id)
+                .map(|e| param.get(&e.adsr_values)),
+            ParamId::ModLfo(..) => None,
+        }
+    }
+
+    // The value has to be in range already
+    fn set_param(&mut self, id: ParamId, value: f32) {
+        match id {
+            ParamId::Osc(osc_id, param) => {
+                if let Some(o) = self.oscillators.get_mut(osc_id) {
+                    o.set_param(param, value);
+                }
+            }
+            ParamId::Envelope(osc_id, param) => {
+                if let Some(o) = self.oscillators.get_mut(osc_id) {
+                    param.set(&mut o.envelope.adsr_values, value);
+                }
+            }
+            ParamId::Effect(osc_id, effect_id, param) => {
+                if let Some(e) = self
+                    .oscillators
+                    .get_mut(osc_id)
+                    .and_then(|o| o.effects.get_mut(effect_id))
+                {
+                    e.set_param(param, value);
+                }
+            }
+            ParamId::ModEnvelope(envelope_id, param) => {
+                if let Some(e) = self.mod_envelopes.get_mut(envelope_id) {
+                    param.set(&mut e.adsr_values, value);
+                }
+            }
+            ParamId::ModLfo(lfo_id, param) => {
+                if let Some(l) = self.mod_lfos.get_mut(lfo_id) {
+                    param.set(l, value);
+                }
+            }
+        }
+    }
+
+    // Same as set_param, but without ramping smoothed parameters to the value. Modulation
+    // changes them every chunk, restarting the ramps would smooth the modulation away
+    fn set_modulated_param(&mut self, id: ParamId, value: f32) {
+        match id {
+            ParamId::Osc(osc_id, param) => {
+                if let Some(o) = self.oscillators.get_mut(osc_id) {
+                    o.set_param_immediate(param, value);
+                }
+            }
+            ParamId::Effect(osc_id, effect_id, param) => {
+                if let Some(e) = self
+                    .oscillators
+                    .get_mut(osc_id)
+                    .and_then(|o| o.effects.get_mut(effect_id))
+                {
+                    e.set_param_immediate(param, value);
+                }
+            }
+            _ => self.set_param(id, value),
+        }
+    }
+
+    fn get_source(&self, source: ModSource, globals: &GlobalSources) -> f32 {
+        match source {
+            ModSource::Lfo(i) => self.lfo_values.get(i).copied().unwrap_or(0.),
+            ModSource::Envelope(i) => self
+                .mod_envelopes
+                .get(i)
+                .map(|e| e.get_last_value())
+                .unwrap_or(0.),
+            ModSource::Velocity => self.velocity,
+            ModSource::KeyTrack => (self.key as f32 - 60.) / 60.,
+            ModSource::ModWheel => globals.mod_wheel,
+            ModSource::Aftertouch => globals.aftertouch,
+            ModSource::Random => self.random,
+        }
+    }
+
+    // Sets every modulated parameter to its base plus whatever is routed to it, then runs
+    // the modulation envelopes through the frames of the chunk about to be rendered
+    fn modulate(
+        &mut self,
+        modulation: &ModMatrix,
+        globals: &GlobalSources,
+        sample_clock: u64,
+        length: usize,
+        channels: u16,
+    ) {
+        for (value, lfo) in self.lfo_values.iter_mut().zip(self.mod_lfos.iter_mut()) {
+            *value = lfo.get_value(sample_clock);
+        }
+
+        for &(id, base) in modulation.bases() {
+            let info = match self.param_info(id) {
+                Some(info) => info,
+                None => continue,
+            };
+
+            let offset: f32 = modulation
+                .routes()
+                .iter()
+                .filter(|r| r.destination == id)
+                .map(|r| r.amount * self.get_source(r.source, globals))
+                .sum();
+            self.set_modulated_param(id, info.from_normalized(info.to_normalized(base) + offset));
+        }
+
+        let channels = channels as u64;
+        for envelope in &mut self.mod_envelopes {
+            for frame in 0..length as u64 / channels {
+                envelope.get_next_sample(sample_clock + frame * channels);
+            }
+        }
+    }
+
+    // Sums all oscillators of this voice into chunk, scaled by scale. Velocity is up to
+    // the envelopes. chunk might be only part of the mixer's block when events split it up
+    fn add_next_chunk(&mut self, chunk: &mut [f32], sample_clock: u64, scale: f32) {
+        let samples = &mut self.buffer[..chunk.len()];
+        for o in &mut self.oscillators {
+            o.write_next_chunk(samples, sample_clock);
+
+            for e in &mut o.effects {
+                e.process_samples(sample_clock, samples);
+            }
+
+            for (out, sample) in chunk.iter_mut().zip(samples.iter()) {
+                *out += sample * scale;
+            }
+        }
+    }
+}
+
+pub struct Mixer {
+    pub channels: u16,
+    pub context: EngineContext,
+
+    voices: Vec<Voice>,
+    voice_stealing: VoiceStealing,
+    // As a frequency ratio
+    pitch_bend: f32,
+    // Frequency of A4
+    master_tune: f32,
+    // Beats per minute
+    tempo: f32,
+    // Last value of every MIDI controller, 0 to 1
+    controllers: [f32; 128],
+    aftertouch: f32,
+
+    modulation: ModMatrix,
+    // State of the generator behind the random modulation source
+    random_state: u32,
+
+    // Messages to play at set sample clock times, sorted by time
+    sequence: Vec<(u64, Message)>,
+    sequence_index: usize,
+    // Timed messages received from the other threads, sorted by time
+    scheduled: VecDeque<(u64, Message)>,
+    // Where other threads can read the sample clock, for timing their messages
+    published_clock: SampleClock,
+
+    // Wavetables get loaded in the background when there's a loader,
+    // otherwise right away (which is fine when rendering offline)
+    wavetable_loader: Option<WavetableLoader>,
+    // Messages the loader had no room for yet, handed to it again every block
+    retiring: VecDeque<Message>,
+}
+
+impl Mixer {
+    /// Every voice gets its own clone of the given oscillators
+    pub fn new<T: Into<Vec<Oscillator>>>(
+        oscillators: T,
+        voice_count: usize,
+        context: EngineContext,
+    ) -> Mixer {
+        assert!(voice_count > 0);
+
+        let oscillators = oscillators.into();
+        let modulation = ModMatrix::default();
+
+        let mut mixer = Mixer {
+            voices: (0..voice_count)
+                .map(|_| {
+                    Voice::new(
+                        oscillators.clone(),
+                        &modulation,
+                        context.block_size as usize,
+                    )
+                })
+                .collect(),
+            voice_stealing: VoiceStealing::Oldest,
+            pitch_bend: 1.,
+            master_tune: DEFAULT_MASTER_TUNE,
+            tempo: DEFAULT_TEMPO,
+            controllers: [0.; 128],
+            aftertouch: 0.,
+            modulation,
+            random_state: 0x9e37_79b9,
+            sequence: vec![],
+            sequence_index: 0,
+            scheduled: VecDeque::with_capacity(MAX_SCHEDULED_MESSAGES),
+            published_clock: SampleClock::default(),
+            channels: 2,
+            context,
+            wavetable_loader: None,
+            retiring: VecDeque::with_capacity(MAX_RETIRING_MESSAGES),
+        };
+        mixer.prepare(mixer.context.sample_rate, mixer.context.block_size);
+        mixer
+    }
+
+    /// Switches everything over to a new sample rate and block size. Allocates, so it
+    /// shouldn't be called while the mixer is being rendered from the audio thread
+    pub fn prepare(&mut self, sample_rate: f32, block_size: u32) {
+        self.context.sample_rate = sample_rate;
+        self.context.block_size = block_size;
+
+        for voice in &mut self.voices {
+            voice.buffer.resize(block_size as usize, 0.);
+            for o in &mut voice.oscillators {
+                o.prepare(sample_rate, block_size as usize);
+                o.set_clock_channels(self.channels);
+            }
+            for e in &mut voice.mod_envelopes {
+                e.prepare(sample_rate, block_size as usize);
+            }
+            for l in &mut voice.mod_lfos {
+                l.prepare(sample_rate, block_size as usize);
+                l.set_channels(self.channels);
+            }
+        }
+    }
+
+    /// Replaces the modulation sources and routes
+    pub fn with_modulation(mut self, packet: &ModulationStatePacket) -> Self {
+        self.set_modulation(packet);
+        self
+    }
+
+    /// Replaces the modulation sources and routes. Allocates, so it shouldn't be called while
+    /// the mixer is being rendered from the audio thread
+    pub fn set_modulation(&mut self, packet: &ModulationStatePacket) {
+        self.modulation.change_routes(ModRouteChange::Clear);
+        self.restore_bases();
+
+        let matrix = ModMatrix::from_state_packet(packet);
+        for voice in &mut self.voices {
+            voice.mod_envelopes = matrix
+                .envelopes
+                .iter()
+                .map(|adsr| ADSREnvelope::new(*adsr))
+                .collect();
+            voice.mod_lfos = matrix.lfos.clone();
+        }
+        self.modulation = matrix;
+        self.set_tempo(self.tempo);
+        self.prepare(self.context.sample_rate, self.context.block_size);
+        self.update_bases();
+    }
+
+    pub fn with_voice_stealing(mut self, voice_stealing: VoiceStealing) -> Self {
+        self.voice_stealing = voice_stealing;
+        self
+    }
+
+    pub fn with_wavetable_loader(mut self, wavetable_loader: WavetableLoader) -> Self {
+        self.wavetable_loader = Some(wavetable_loader);
+        self
+    }
+
+    /// The wavetable of every oscillator, in order
+    pub fn get_wavetables(&self) -> Vec<Arc<Wavetable>> {
+        self.voices[0]
+            .oscillators
+            .iter()
+            .map(|o| o.wavetable.clone())
+            .collect()
+    }
+
+    fn set_wavetable(&mut self, id: usize, wavetable: Arc<Wavetable>) {
+        // All voices share the same table
+        self.oscillators_mut(id)
+            .for_each(|o| o.change_wavetable(wavetable.clone()));
+    }
+
+    // Frees the message off the audio thread when there's a loader, right away otherwise.
+    // Waits for a later block when the loader is behind
+    fn retire(&mut self, message: Message) {
+        if let Some(loader) = &self.wavetable_loader {
+            if !self.retiring.is_empty() {
+                if self.retiring.len() < MAX_RETIRING_MESSAGES {
+                    self.retiring.push_back(message);
+                }
+            } else if let Err(message) = loader.retire(message) {
+                self.retiring.push_back(message);
+            }
+        }
+    }
+
+    fn retry_retiring(&mut self) {
+        if let Some(loader) = &self.wavetable_loader {
+            while let Some(message) = self.retiring.pop_front() {
+                if let Err(message) = loader.retire(message) {
+                    self.retiring.push_front(message);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn receive_wavetables(&mut self) {
+        while let Some((id, wavetable)) = self
+            .wavetable_loader
+            .as_ref()
+            .and_then(|loader| loader.try_receive())
+        {
+            self.set_wavetable(id, wavetable);
+        }
+    }
+
+    /// The sample clock for other threads, Message::Timed is timed by it
+    pub fn get_sample_clock(&self) -> SampleClock {
+        self.published_clock.clone()
+    }
+
+    pub fn with_master_tune(mut self, master_tune: f32) -> Self {
+        self.master_tune = master_tune;
+        self
+    }
+
+    /// Time it takes oscillators to fade into a newly loaded wavetable, 0 switches instantly
+    pub fn with_wavetable_crossfade(mut self, seconds: f32) -> Self {
+        for voice in &mut self.voices {
+            for o in &mut voice.oscillators {
+                o.set_crossfade_time(seconds);
+            }
+        }
+        self
+    }
+
+    /// Time it takes gain and filter changes to ramp to their new values, 0 applies them instantly
+    pub fn with_parameter_smoothing(mut self, seconds: f32) -> Self {
+        for voice in &mut self.voices {
+            for o in &mut voice.oscillators {
+                o.set_smoothing_time(seconds);
+            }
+        }
+        self
+    }
+
+    // Empty for unknown ids
+    fn oscillators_mut(&mut self, id: usize) -> impl Iterator<Item = &mut Oscillator> {
+        self.voices
+            .iter_mut()
+            .filter_map(move |v| v.oscillators.get_mut(id))
+    }
+
+    fn has_oscillator(&self, id: usize) -> bool {
+        self.voices
+            .first()
+            .is_some_and(|v| id < v.oscillators.len())
+    }
+
+    fn note_on(&mut self, note: u8, velocity: f32, sample_clock: u64) {
+        // Retrigger the voice already playing this note, otherwise take a free one
+        // or steal one when all voices are busy
+        let voice_index = match self.voices.iter().position(|v| v.note == Some(note)) {
+            Some(index) => index,
+            None => match self.voices.iter().position(|v| !v.is_active()) {
+                Some(index) => index,
+                None => self.find_voice_to_steal(),
+            },
+        };
+
+        let pitch_bend = self.pitch_bend;
+        let master_tune = self.master_tune;
+        let random = next_random(&mut self.random_state);
+        self.voices[voice_index].note_on(
+            note,
+            velocity,
+            sample_clock,
+            pitch_bend,
+            master_tune,
+            random,
+        );
+    }
+
+    fn set_master_tune(&mut self, master_tune: f32) {
+        self.master_tune = master_tune;
+
+        // Retune the notes being held, released ones just ring out as they were
+        let pitch_bend = self.pitch_bend;
+        for voice in &mut self.voices {
+            if let Some(note) = voice.note {
+                voice.frequency = midi_note_to_frequency(note, master_tune);
+                voice.set_pitch_bend(pitch_bend);
+            }
+        }
+    }
+
+    fn set_tempo(&mut self, bpm: f32) {
+        self.tempo = bpm;
+        for voice in &mut self.voices {
+            voice.oscillators.iter_mut().for_each(|o| o.set_tempo(bpm));
+            voice.mod_lfos.iter_mut().for_each(|l| l.set_tempo(bpm));
+        }
+    }
+
+    fn set_pitch_bend(&mut self, bend: f32) {
+        self.pitch_bend = 2_f32.powf(bend * PITCH_BEND_RANGE / 12.);
+
+        let pitch_bend = self.pitch_bend;
+        self.voices
+            .iter_mut()
+            .filter(|v| v.is_active())
+            .for_each(|v| v.set_pitch_bend(pitch_bend));
+    }
+
+    fn note_off(&mut self, note: u8, sample_clock: u64) {
+        self.voices
+            .iter_mut()
+            .filter(|v| v.note == Some(note))
+            .for_each(|v| v.note_off(sample_clock));
+    }
+
+    fn find_voice_to_steal(&self) -> usize {
+        let voices = self.voices.iter().enumerate();
+        let stolen = match self.voice_stealing {
+            VoiceStealing::Oldest => voices.min_by_key(|(_, v)| v.started_at),
+            VoiceStealing::Quietest => {
+                voices.min_by(|(_, a), (_, b)| a.get_level().total_cmp(&b.get_level()))
+            }
+        };
+
+        stolen.map(|(index, _)| index).unwrap_or(0)
+    }
+
+    fn schedule(&mut self, time: u64, message: Message, sample_clock: u64) {
+        // Making room would allocate, so once it's full they're played as they come in
+        if self.scheduled.len() == self.scheduled.capacity() {
+            self.handle_message_at(message, sample_clock);
+            return;
+        }
+
+        // Onto the start of a frame, splitting a block mid-frame would swap the channels
+        let time = time - time % self.channels as u64;
+
+        // After any message with the same time, so they keep the order they were sent in
+        let index = self.scheduled.partition_point(|(t, _)| *t <= time);
+        self.scheduled.insert(index, (time, message));
+    }
+
+    /// Schedules messages to be handled at exactly the frame they are timed at,
+    /// counting from now. Replaces whatever sequence was playing before.
+    pub fn play_sequence(&mut self, mut events: Vec<(u64, Message)>) {
+        // The sample clock runs once per sample of every channel, not per frame
+        let start = self.context.sample_clock;
+        let channels = self.channels as u64;
+        events.sort_by_key(|(frame, _)| *frame);
+
+        self.sequence = events
+            .into_iter()
+            .map(|(frame, message)| (start + frame * channels, message))
+            .collect();
+        self.sequence_index = 0;
+    }
+
+    // Sample clock time of the next sequenced message, if there's any left
+    fn next_sequence_time(&self) -> Option<u64> {
+        self.sequence
+            .get(self.sequence_index)
+            .map(|(time, _)| *time)
+    }
+
+    fn next_scheduled_time(&self) -> Option<u64> {
+        self.scheduled.front().map(|(time, _)| *time)
+    }
+
+    // Sample clock time of whichever sequenced or scheduled message comes first
+    fn next_event_time(&self) -> Option<u64> {
+        match (self.next_sequence_time(), self.next_scheduled_time()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn handle_events_until(&mut self, sample_clock: u64) {
+        while let Some(time) = self.next_event_time() {
+            if time > sample_clock {
+                break;
+            }
+
+            // The sequence goes first when both have a message at the same time
+            let message = if self.next_sequence_time() == Some(time) {
+                self.sequence_index += 1;
+                self.sequence[self.sequence_index - 1].1.clone()
+            } else {
+                self.scheduled.pop_front().unwrap().1
+            };
+            self.handle_message_at(message, sample_clock);
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Message) {
+        self.handle_message_at(message, self.context.sample_clock);
+    }
+
+    fn handle_message_at(&mut self, message: Message, sample_clock: u64) {
+        match message {
+            Message::NoteOn(note, velocity) => {
+                self.note_on(note, velocity, sample_clock);
+            }
+            Message::NoteOff(note) => {
+                self.note_off(note, sample_clock);
+            }
+            Message::PitchBend(bend) => self.set_pitch_bend(bend),
+            Message::MasterTune(master_tune) => self.set_master_tune(master_tune),
+            Message::Tempo(bpm) => self.set_tempo(bpm),
+            Message::ModWheel(value) => {
+                self.controllers[MOD_WHEEL_CC as usize] = value;
+            }
+            Message::ControlChange(controller, value) => {
+                self.controllers[controller as usize & 0x7f] = value;
+            }
+            Message::Aftertouch(value) => self.aftertouch = value,
+            Message::SetParam(id, value) => self.set_param(id, value),
+            Message::ModRoute(change) => {
+                self.modulation.change_routes(change);
+                self.restore_bases();
+                self.update_bases();
+            }
+            // Unknown oscillators are ignored
+            Message::SampleChange(id, sample) if !self.has_oscillator(id) => {
+                self.retire(Message::SampleChange(id, sample))
+            }
+            Message::SampleChange(id, sample) => match &self.wavetable_loader {
+                Some(loader) => {
+                    if let Err((id, sample)) = loader.request(id, sample) {
+                        self.retire(Message::SampleChange(id, sample));
+                    }
+                }
+                // The current table keeps playing if the new one can't be loaded
+                None => match Wavetable::create_wavetable(sample) {
+                    Ok(wavetable) => self.set_wavetable(id, Arc::new(wavetable)),
+                    Err(e) => error!("Failed to load wavetable: {}", e),
+                },
+            },
+            Message::Timed(time, mut timed) => {
+                // Taken out without freeing the box, which goes to the loader thread instead
+                let message = std::mem::replace(&mut *timed, Message::Error(String::new()));
+                self.retire(Message::Timed(time, timed));
+                self.schedule(time, message, sample_clock);
+            }
+            // Only ever sent to the GUI
+            message @ (Message::WavetableError(..) | Message::Error(_)) => self.retire(message),
+        }
+    }
+
+    /// None if there's no such parameter in this patch
+    pub fn param_info(&self, id: ParamId) -> Option<ParamInfo> {
+        match id {
+            ParamId::ModLfo(lfo_id, param) => {
+                self.modulation.lfos.get(lfo_id).map(|_| *param.info())
+            }
+            // Every voice has the same patch, the first one stands in for all of them
+            _ => self.voices.first()?.param_info(id),
+        }
+    }
+
+    /// The unmodulated value, None if there's no such parameter in this patch
+    pub fn get_param(&self, id: ParamId) -> Option<f32> {
+        if let Some(base) = self.modulation.get_base(id) {
+            return Some(base);
+        }
+
+        match id {
+            ParamId::ModLfo(lfo_id, param) => {
+                self.modulation.lfos.get(lfo_id).map(|l| param.get(l))
+            }
+            _ => self.voices.first()?.get_param(id),
+        }
+    }
+
+    /// Sets the parameter on every voice, clamped to its range. Modulated parameters take
+    /// it as their new base. Unknown parameters are ignored
+    pub fn set_param(&mut self, id: ParamId, value: f32) {
+        let value = match self.param_info(id) {
+            Some(info) => info.clamp(value),
+            None => return,
+        };
+
+        match id {
+            ParamId::ModEnvelope(envelope_id, param) => {
+                param.set(&mut self.modulation.envelopes[envelope_id], value)
+            }
+            ParamId::ModLfo(lfo_id, param) => param.set(&mut self.modulation.lfos[lfo_id], value),
+            _ => {}
+        }
+        // Applied with the modulation on top at the start of the next chunk
+        if self.modulation.set_base(id, value) {
+            return;
+        }
+
+        self.voices.iter_mut().for_each(|v| v.set_param(id, value));
+    }
+
+    // Puts parameters back to their unmodulated values once no route points at them anymore
+    fn restore_bases(&mut self) {
+        while let Some((id, base)) = self.modulation.take_unused_base() {
+            self.voices.iter_mut().for_each(|v| v.set_param(id, base));
+        }
+    }
+
+    // Remembers the unmodulated value of every parameter that just got a route
+    fn update_bases(&mut self) {
+        for i in 0..self.modulation.routes().len() {
+            let id = self.modulation.routes()[i].destination;
+            // The LFOs' settings live in the matrix, routes can't point back at them
+            if matches!(id, ParamId::ModLfo(..)) || self.modulation.get_base(id).is_some() {
+                continue;
+            }
+            if let Some(value) = self.get_param(id) {
+                self.modulation.add_base(id, value);
+            }
+        }
+    }
+
+    /// Renders the next samples into output, interleaved by channel. output has to hold
+    /// whole frames, anything longer than the block size gets rendered a block at a time.
+    /// Allocates nothing, so it's safe to call from the audio callback
+    pub fn process_block(&mut self, output: &mut [f32]) {
+        self.retry_retiring();
+        self.receive_wavetables();
+
+        let block_size = self.context.block_size as usize;
+        for block in output.chunks_mut(block_size) {
+            self.render_block(block);
+        }
+        self.published_clock.set(self.context.sample_clock);
+    }
+
+    fn render_block(&mut self, block: &mut [f32]) {
+        // Add up all the playing voices, divide by # of osc per voice
+        block.iter_mut().for_each(|s| *s = 0.);
+
+        let block_start = self.context.sample_clock;
+        let block_end = block_start + block.len() as u64;
+
+        // Split the block up at every sequenced or timed message so they land on their exact sample
+        let mut start = block_start;
+        while start < block_end {
+            self.handle_events_until(start);
+
+            let end = match self.next_event_time() {
+                Some(time) if time < block_end => time,
+                _ => block_end,
+            };
+            let range = (start - block_start) as usize..(end - block_start) as usize;
+
+            let modulated = !self.modulation.routes().is_empty();
+            let globals = if modulated {
+                self.next_global_sources()
+            } else {
+                GlobalSources::default()
+            };
+
+            for voice in self.voices.iter_mut().filter(|v| v.is_active()) {
+                if modulated {
+                    voice.modulate(
+                        &self.modulation,
+                        &globals,
+                        start,
+                        range.len(),
+                        self.channels,
+                    );
+                }
+
+                let scale = 1. / voice.oscillators.len() as f32;
+                voice.add_next_chunk(&mut block[range.clone()], start, scale);
+            }
+
+            start = end;
+        }
+
+        self.context.advance_sample_clock(block.len() as u64);
+    }
+
+    fn next_global_sources(&self) -> GlobalSources {
+        GlobalSources {
+            mod_wheel: self.controllers[MOD_WHEEL_CC as usize],
+            aftertouch: self.aftertouch,
+        }
+    }
+
+    pub fn get_state_packet(&self) -> MixerStatePacket {
+        // All voices share the same patch, so the first one speaks for all of them
+        let mut packet = MixerStatePacket {
+            oscillators: self.voices[0]
+                .oscillators
+                .iter()
+                .map(|o| o.get_state_packet())
+                .collect(),
+            modulation: self.modulation.get_state_packet(),
+        };
+
+        // Without the modulation currently applied on top
+        for &(id, base) in self.modulation.bases() {
+            packet.set_param(id, base);
+        }
+        packet
+    }
+}
@@ -0,0 +1,212 @@
+//! Routes modulation sources (LFOs, envelopes, controllers) to any parameter
+//!
+//! Every route adds its source, scaled by a bipolar amount, onto its destination in the
+//! parameter's normalized 0 to 1 range, so an amount of 1 sweeps across the whole range.
+//! The mixer evaluates the routes for every voice at the start of each chunk it renders.
+
+use serde::{Deserialize, Serialize};
+
+use effects::adsr::ADSR;
+use effects::lfo::{Lfo, LfoStatePacket};
+
+use crate::params::ParamId;
+
+pub const MAX_MOD_ROUTES: usize = 16;
+pub const MOD_LFO_COUNT: usize = 2;
+pub const MOD_ENVELOPE_COUNT: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ModSource {
+    // Every voice runs its own, so retriggering ones start over with each of its notes.
+    // -1 to 1 (0 to 1 when unipolar)
+    Lfo(usize),
+    // Triggered and released with every note, 0 to 1
+    Envelope(usize),
+    // 0 to 1
+    Velocity,
+    // Octaves away from middle C, divided by 5 so the keyboard spans -1 to 1
+    KeyTrack,
+    // 0 to 1
+    ModWheel,
+    // Channel pressure, 0 to 1
+    Aftertouch,
+    // Picked for every note, -1 to 1
+    Random,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub destination: ParamId,
+    // -1 to 1, in proportions of the destination's whole range
+    pub amount: f32,
+}
+
+/// Changes to the list of routes. Routes are addressed by their index in the list
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ModRouteChange {
+    // Ignored when there are MAX_MOD_ROUTES already
+    Add(ModRoute),
+    Set(usize, ModRoute),
+    Remove(usize),
+    Clear,
+}
+
+impl ModRouteChange {
+    /// Unknown indices are ignored
+    pub fn apply(self, routes: &mut Vec<ModRoute>) {
+        match self {
+            ModRouteChange::Add(route) => {
+                if routes.len() < MAX_MOD_ROUTES {
+                    routes.push(route);
+                }
+            }
+            ModRouteChange::Set(index, route) => {
+                if let Some(r) = routes.get_mut(index) {
+                    *r = route;
+                }
+            }
+            ModRouteChange::Remove(index) => {
+                if index < routes.len() {
+                    routes.remove(index);
+                }
+            }
+            ModRouteChange::Clear => routes.clear(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModulationStatePacket {
+    pub lfos: Vec<LfoStatePacket>,
+    pub envelopes: Vec<ADSR>,
+    pub routes: Vec<ModRoute>,
+}
+
+impl Default for ModulationStatePacket {
+    fn default() -> Self {
+        ModulationStatePacket {
+            lfos: vec![default_lfo(); MOD_LFO_COUNT],
+            envelopes: vec![ADSR::default(); MOD_ENVELOPE_COUNT],
+            routes: vec![],
+        }
+    }
+}
+
+fn default_lfo() -> LfoStatePacket {
+    LfoStatePacket {
+        frequency: 1.,
+        ..LfoStatePacket::default()
+    }
+}
+
+/// Values of the sources that are the same for every voice, for one chunk
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct GlobalSources {
+    pub mod_wheel: f32,
+    pub aftertouch: f32,
+}
+
+#[derive(Clone)]
+pub struct ModMatrix {
+    // Settings of every voice's LFOs
+    pub(crate) lfos: Vec<Lfo>,
+    // Settings of every voice's modulation envelopes
+    pub(crate) envelopes: Vec<ADSR>,
+    routes: Vec<ModRoute>,
+    // Unmodulated value of every parameter a route points at
+    bases: Vec<(ParamId, f32)>,
+}
+
+impl Default for ModMatrix {
+    fn default() -> Self {
+        ModMatrix::from_state_packet(&ModulationStatePacket::default())
+    }
+}
+
+impl ModMatrix {
+    /// Missing LFOs and envelopes get their defaults, extra ones are dropped
+    pub fn from_state_packet(packet: &ModulationStatePacket) -> ModMatrix {
+        let lfos = (0..MOD_LFO_COUNT)
+            .map(|i| Lfo::from_state_packet(packet.lfos.get(i).unwrap_or(&default_lfo())))
+            .collect();
+        let envelopes = (0..MOD_ENVELOPE_COUNT)
+            .map(|i| packet.envelopes.get(i).copied().unwrap_or_default())
+            .collect();
+
+        // Room for every route up front, so changing them on the audio thread doesn't allocate
+        let mut routes = Vec::with_capacity(MAX_MOD_ROUTES);
+        routes.extend(packet.routes.iter().take(MAX_MOD_ROUTES));
+
+        ModMatrix {
+            lfos,
+            envelopes,
+            routes,
+            bases: Vec::with_capacity(MAX_MOD_ROUTES),
+        }
+    }
+
+    pub fn routes(&self) -> &[ModRoute] {
+        &self.routes
+    }
+
+    pub(crate) fn change_routes(&mut self, change: ModRouteChange) {
+        change.apply(&mut self.routes);
+    }
+
+    pub fn is_modulated(&self, id: ParamId) -> bool {
+        self.routes.iter().any(|r| r.destination == id)
+    }
+
+    /// Unmodulated value of a parameter, None when nothing modulates it
+    pub fn get_base(&self, id: ParamId) -> Option<f32> {
+        self.bases.iter().find(|(b, _)| *b == id).map(|(_, v)| *v)
+    }
+
+    pub(crate) fn bases(&self) -> &[(ParamId, f32)] {
+        &self.bases
+    }
+
+    /// Only updates parameters that already have a base, returns whether it did
+    pub(crate) fn set_base(&mut self, id: ParamId, value: f32) -> bool {
+        match self.bases.iter_mut().find(|(b, _)| *b == id) {
+            Some(base) => {
+                base.1 = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn add_base(&mut self, id: ParamId, value: f32) {
+        if !self.set_base(id, value) && self.bases.len() < MAX_MOD_ROUTES {
+            self.bases.push((id, value));
+        }
+    }
+
+    /// Bases of parameters no route points at anymore, so they can be put back
+    pub(crate) fn take_unused_base(&mut self) -> Option<(ParamId, f32)> {
+        let index = self
+            .bases
+            .iter()
+            .position(|(id, _)| !self.routes.iter().any(|r| r.destination == *id))?;
+        Some(self.bases.swap_remove(index))
+    }
+
+    pub fn get_state_packet(&self) -> ModulationStatePacket {
+        ModulationStatePacket {
+            lfos: self.lfos.iter().map(|l| l.get_state_packet()).collect(),
+            envelopes: self.envelopes.clone(),
+            routes: self.routes.clone(),
+        }
+    }
+}
+
+/// Uniformly distributed between -1 and 1, from a xorshift generator
+pub(crate) fn next_random(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    (*state as f32 / u32::MAX as f32) * 2. - 1.
+}
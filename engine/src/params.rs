@@ -0,0 +1,182 @@
+//! Every automatable parameter of the synth, addressed by a stable id
+//!
+//! Presets, the GUI and anything else driving the mixer set parameters with
+//! `Message::SetParam(id, value)`, and can look up a parameter's name, range, default
+//! and unit through `MixerStatePacket::param_info`.
+
+use serde::{Deserialize, Serialize};
+
+use effects::adsr::ADSR;
+use effects::lfo::Lfo;
+use effects::{ParamInfo, Unit};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum OscParam {
+    Gain,
+    // 0 to 1, from the first frame of the wavetable to the last
+    Position,
+    Coarse,
+    Fine,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum EnvelopeParam {
+    Delay,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Hold,
+    AttackCurve,
+    DecayCurve,
+    ReleaseCurve,
+    VelocityToLevel,
+    VelocityToTime,
+}
+
+// In the same order as Lfo::params
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum LfoParam {
+    Waveform,
+    Frequency,
+    // Where in the cycle it starts
+    Phase,
+    Mode,
+    Polarity,
+    FadeIn,
+    // Note division of the tempo, or off
+    Sync,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum ParamId {
+    // osc_id, param
+    Osc(usize, OscParam),
+    Envelope(usize, EnvelopeParam),
+    // osc_id, effect_id, index into the effect's Processor::params
+    Effect(usize, usize, usize),
+    // Sources of the modulation matrix, by lfo/envelope index
+    ModLfo(usize, LfoParam),
+    ModEnvelope(usize, EnvelopeParam),
+}
+
+pub const OSC_PARAMS: [OscParam; 4] = [
+    OscParam::Gain,
+    OscParam::Position,
+    OscParam::Coarse,
+    OscParam::Fine,
+];
+
+pub const ENVELOPE_PARAMS: [EnvelopeParam; 11] = [
+    EnvelopeParam::Delay,
+    EnvelopeParam::Attack,
+    EnvelopeParam::Hold,
+    EnvelopeParam::Decay,
+    EnvelopeParam::Sustain,
+    EnvelopeParam::Release,
+    EnvelopeParam::AttackCurve,
+    EnvelopeParam::DecayCurve,
+    EnvelopeParam::ReleaseCurve,
+    EnvelopeParam::VelocityToLevel,
+    EnvelopeParam::VelocityToTime,
+];
+
+pub const LFO_PARAMS: [LfoParam; 7] = [
+    LfoParam::Waveform,
+    LfoParam::Frequency,
+    LfoParam::Phase,
+    LfoParam::Mode,
+    LfoParam::Polarity,
+    LfoParam::FadeIn,
+    LfoParam::Sync,
+];
+
+static OSC_PARAM_INFO: [ParamInfo; 4] = [
+    ParamInfo::new("Gain", 0., 1., 0.5),
+    ParamInfo::new("Position", 0., 1., 0.),
+    ParamInfo::new("Coarse", -24., 24., 0.).with_unit(Unit::Semitones),
+    ParamInfo::new("Fine", -100., 100., 0.).with_unit(Unit::Cents),
+];
+
+// In declaration order of EnvelopeParam
+static ENVELOPE_PARAM_INFO: [ParamInfo; 11] = [
+    ParamInfo::new("Delay", 0., 10., 0.)
+        .with_unit(Unit::Seconds)
+        .with_skew(0.5),
+    ParamInfo::new("Attack", 0., 10., 0.2)
+        .with_unit(Unit::Seconds)
+        .with_skew(0.5),
+    ParamInfo::new("Decay", 0., 10., 0.1)
+        .with_unit(Unit::Seconds)
+        .with_skew(0.5),
+    ParamInfo::new("Sustain", 0., 1., 1.),
+    ParamInfo::new("Release", 0., 10., 2.)
+        .with_unit(Unit::Seconds)
+        .with_skew(0.5),
+    ParamInfo::new("Hold", 0., 10., 0.)
+        .with_unit(Unit::Seconds)
+        .with_skew(0.5),
+    ParamInfo::new("Attack curve", -1., 1., 0.),
+    ParamInfo::new("Decay curve", -1., 1., 0.),
+    ParamInfo::new("Release curve", -1., 1., 0.),
+    ParamInfo::new("Velocity to level", 0., 1., 1.),
+    ParamInfo::new("Velocity to time", -1., 1., 0.),
+];
+
+impl OscParam {
+    pub fn info(self) -> &'static ParamInfo {
+        &OSC_PARAM_INFO[self as usize]
+    }
+}
+
+impl EnvelopeParam {
+    pub fn info(self) -> &'static ParamInfo {
+        &ENVELOPE_PARAM_INFO[self as usize]
+    }
+
+    pub fn get(self, adsr: &ADSR) -> f32 {
+        match self {
+            EnvelopeParam::Delay => adsr.delay,
+            EnvelopeParam::Attack => adsr.attack,
+            EnvelopeParam::Decay => adsr.decay,
+            EnvelopeParam::Sustain => adsr.sustain,
+            EnvelopeParam::Release => adsr.release,
+            EnvelopeParam::Hold => adsr.hold,
+            EnvelopeParam::AttackCurve => adsr.attack_curve,
+            EnvelopeParam::DecayCurve => adsr.decay_curve,
+            EnvelopeParam::ReleaseCurve => adsr.release_curve,
+            EnvelopeParam::VelocityToLevel => adsr.velocity_to_level,
+            EnvelopeParam::VelocityToTime => adsr.velocity_to_time,
+        }
+    }
+
+    pub fn set(self, adsr: &mut ADSR, value: f32) {
+        match self {
+            EnvelopeParam::Delay => adsr.delay = value,
+            EnvelopeParam::Attack => adsr.attack = value,
+            EnvelopeParam::Decay => adsr.decay = value,
+            EnvelopeParam::Sustain => adsr.sustain = value,
+            EnvelopeParam::Release => adsr.release = value,
+            EnvelopeParam::Hold => adsr.hold = value,
+            EnvelopeParam::AttackCurve => adsr.attack_curve = value,
+            EnvelopeParam::DecayCurve => adsr.decay_curve = value,
+            EnvelopeParam::ReleaseCurve => adsr.release_curve = value,
+            EnvelopeParam::VelocityToLevel => adsr.velocity_to_level = value,
+            EnvelopeParam::VelocityToTime => adsr.velocity_to_time = value,
+        }
+    }
+}
+
+impl LfoParam {
+    pub fn info(self) -> &'static ParamInfo {
+        &Lfo::params()[self as usize]
+    }
+
+    pub fn get(self, lfo: &Lfo) -> f32 {
+        lfo.get_param(self as usize).unwrap_or(0.)
+    }
+
+    pub fn set(self, lfo: &mut Lfo, value: f32) {
+        lfo.set_param(self as usize, value);
+    }
+}
@@ -0,0 +1,63 @@
+//! The patches a mixer can start out with
+
+use effects::filters::{FilterType, ModulatedFilter, StateVariableTPTFilter};
+use effects::lfo::{Lfo, LfoType};
+use effects::Effect;
+
+use crate::context::EngineContext;
+use crate::mixer::Mixer;
+use crate::preset::Preset;
+use crate::synths::{Oscillator, Sample, Wavetable};
+use crate::EngineError;
+
+/// Notes that can play at once, unless asked for another count
+pub const DEFAULT_VOICE_COUNT: usize = 8;
+
+/// Builds the patch from the preset file if there's one, the default patch otherwise.
+/// The same is used for real time playback and offline rendering
+pub fn build_mixer(
+    samples: &[Sample],
+    sample_rate: u32,
+    voice_count: usize,
+    preset: Option<&str>,
+) -> Result<Mixer, EngineError> {
+    if voice_count == 0 {
+        return Err(EngineError::NoVoices);
+    }
+
+    match preset {
+        Some(path) => Preset::load(path)?.build_mixer(samples, sample_rate, voice_count),
+        None => build_default_mixer(samples, sample_rate, voice_count),
+    }
+}
+
+/// Two oscillators playing the first sample, each through its own low pass filter
+pub fn build_default_mixer(
+    samples: &[Sample],
+    sample_rate: u32,
+    voice_count: usize,
+) -> Result<Mixer, EngineError> {
+    let sample = samples.first().ok_or(EngineError::NoSamples)?;
+
+    let wavetable = Wavetable::create_wavetable(sample.clone())?;
+    let mut osc = Oscillator::new(0.5, 1440., wavetable, sample_rate as f32);
+    osc.add_effect(Effect::ModulatedFilter(ModulatedFilter::new(
+        Lfo::new(LfoType::Sine, 0.5, 0.),
+        StateVariableTPTFilter::new(sample_rate as f32, 2000., FilterType::LowPass),
+        2000.,
+    )));
+
+    let wavetable2 = Wavetable::create_wavetable(sample.clone())?;
+    let mut osc2 = Oscillator::new(0.2, 440., wavetable2, sample_rate as f32);
+    osc2.add_effect(Effect::ModulatedFilter(ModulatedFilter::new(
+        Lfo::new(LfoType::Sine, 0., 0.),
+        StateVariableTPTFilter::new(sample_rate as f32, 2000., FilterType::LowPass),
+        2000.,
+    )));
+
+    Ok(Mixer::new(
+        vec![osc, osc2],
+        voice_count,
+        EngineContext::new(sample_rate),
+    ))
+}
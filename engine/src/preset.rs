@@ -0,0 +1,330 @@
+//! Saving and loading the whole synth patch as JSON
+//!
+//! Every field falls back to a default when it's missing, so presets written by older
+//! versions keep loading as new parameters get added.
+
+use std::fs;
+use std::mem::discriminant;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use effects::adsr::ADSR;
+use effects::lfo::Lfo;
+use effects::{Effect, EffectStatePacket, Processor};
+
+use crate::context::EngineContext;
+use crate::messages::Message;
+use crate::mixer::{Mixer, MixerStatePacket};
+use crate::modulation::{ModRouteChange, ModulationStatePacket};
+use crate::params::{OscParam, ParamId, ENVELOPE_PARAMS, LFO_PARAMS};
+use crate::synths::{Oscillator, Sample, Wavetable};
+use crate::EngineError;
+
+pub const PRESET_VERSION: u32 = 1;
+pub const PRESETS_PATH: &str = "presets/";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscillatorPreset {
+    // Name of the sample inside the wavetable directory
+    pub wavetable: String,
+    pub gain: f32,
+    // 0 to 1 through the wavetable's frames
+    pub position: f32,
+    // Semitones and cents on top of the played note
+    pub coarse: f32,
+    pub fine: f32,
+    // In seconds, engine defaults are used when missing
+    pub envelope: Option<ADSR>,
+    // Including the internal low pass filter, engine defaults are used when empty
+    pub effects: Vec<EffectStatePacket>,
+}
+
+impl Default for OscillatorPreset {
+    fn default() -> Self {
+        OscillatorPreset {
+            wavetable: String::new(),
+            gain: 0.5,
+            position: 0.,
+            coarse: 0.,
+            fine: 0.,
+            envelope: None,
+            effects: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub version: u32,
+    pub oscillators: Vec<OscillatorPreset>,
+    // Modulation LFOs, envelopes and routes
+    pub modulation: ModulationStatePacket,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Preset {
+            version: PRESET_VERSION,
+            oscillators: vec![],
+            modulation: ModulationStatePacket::default(),
+        }
+    }
+}
+
+impl Preset {
+    pub fn from_state_packet(packet: &MixerStatePacket) -> Preset {
+        Preset {
+            version: PRESET_VERSION,
+            oscillators: packet
+                .oscillators
+                .iter()
+                .map(|o| OscillatorPreset {
+                    wavetable: o.name.clone(),
+                    gain: o.gain,
+                    position: o.position,
+                    coarse: o.coarse,
+                    fine: o.fine,
+                    envelope: Some(o.adsr),
+                    effects: o.effects.clone(),
+                })
+                .collect(),
+            modulation: packet.modulation.clone(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Preset, EngineError> {
+        let contents = fs::read_to_string(path.as_ref()).map_err(|source| EngineError::Io {
+            path: path.as_ref().display().to_string(),
+            source,
+        })?;
+        let preset: Preset =
+            serde_json::from_str(&contents).map_err(|source| EngineError::InvalidPreset {
+                path: path.as_ref().display().to_string(),
+                source,
+            })?;
+
+        if preset.version > PRESET_VERSION {
+            warn!(
+                "{} was saved by a newer version (preset version {}), some settings might be ignored",
+                path.as_ref().display(),
+                preset.version
+            );
+        }
+
+        Ok(preset)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Builds a new mixer playing this preset
+    pub fn build_mixer(
+        &self,
+        samples: &[Sample],
+        sample_rate: u32,
+        voice_count: usize,
+    ) -> Result<Mixer, EngineError> {
+        if self.oscillators.is_empty() {
+            return Err(EngineError::EmptyPreset);
+        }
+
+        let oscillators = self
+            .oscillators
+            .iter()
+            .map(|o| {
+                let sample = find_sample(samples, &o.wavetable)
+                    .or_else(|| samples.first())
+                    .ok_or(EngineError::NoSamples)?;
+                let wavetable = Wavetable::create_wavetable(sample.clone())?;
+
+                let mut osc = Oscillator::new(o.gain, 440., wavetable, sample_rate as f32);
+                osc.set_position(o.position);
+                osc.set_coarse(o.coarse);
+                osc.set_fine(o.fine);
+                if let Some(envelope) = &o.envelope {
+                    osc.envelope.adsr_values = *envelope;
+                }
+                if !o.effects.is_empty() {
+                    osc.effects = o
+                        .effects
+                        .iter()
+                        .map(|e| Effect::from_state_packet(e, sample_rate as f32))
+                        .collect();
+                }
+
+                Ok(osc)
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
+
+        Ok(
+            Mixer::new(oscillators, voice_count, EngineContext::new(sample_rate))
+                .with_modulation(&self.modulation),
+        )
+    }
+
+    /// Whether a running mixer in this state has the same oscillators with the same effects in
+    /// the same order, which to_messages needs. Presets without effects keep the mixer's own
+    pub fn matches_layout(&self, packet: &MixerStatePacket) -> bool {
+        self.oscillators.len() == packet.oscillators.len()
+            && self
+                .oscillators
+                .iter()
+                .zip(&packet.oscillators)
+                .all(|(preset, osc)| {
+                    preset.effects.is_empty()
+                        || (preset.effects.len() == osc.effects.len()
+                            && preset
+                                .effects
+                                .iter()
+                                .zip(&osc.effects)
+                                .all(|(a, b)| discriminant(a) == discriminant(b)))
+                })
+    }
+
+    /// Messages that turn a running mixer with the same oscillator/effect layout into this preset,
+    /// check matches_layout first
+    pub fn to_messages(&self, samples: &[Sample]) -> Vec<Message> {
+        let mut messages = vec![];
+
+        for (id, o) in self.oscillators.iter().enumerate() {
+            match find_sample(samples, &o.wavetable) {
+                Some(sample) => messages.push(Message::SampleChange(id, sample.clone())),
+                None => warn!(
+                    "Wavetable {} not found, keeping the current one",
+                    o.wavetable
+                ),
+            }
+
+            let osc_params = [
+                (OscParam::Gain, o.gain),
+                (OscParam::Position, o.position),
+                (OscParam::Coarse, o.coarse),
+                (OscParam::Fine, o.fine),
+            ];
+            messages.extend(
+                osc_params
+                    .iter()
+                    .map(|(param, value)| Message::SetParam(ParamId::Osc(id, *param), *value)),
+            );
+
+            if let Some(envelope) = &o.envelope {
+                messages.extend(ENVELOPE_PARAMS.iter().map(|param| {
+                    Message::SetParam(ParamId::Envelope(id, *param), param.get(envelope))
+                }));
+            }
+
+            for (effect_id, effect) in o.effects.iter().enumerate() {
+                messages.extend((0..effect.params().len()).filter_map(|param| {
+                    effect.get_param(param).map(|value| {
+                        Message::SetParam(ParamId::Effect(id, effect_id, param), value)
+                    })
+                }));
+            }
+        }
+
+        for (lfo_id, lfo) in self.modulation.lfos.iter().enumerate() {
+            let lfo = Lfo::from_state_packet(lfo);
+            messages.extend(
+                LFO_PARAMS.iter().map(|param| {
+                    Message::SetParam(ParamId::ModLfo(lfo_id, *param), param.get(&lfo))
+                }),
+            );
+        }
+        for (envelope_id, envelope) in self.modulation.envelopes.iter().enumerate() {
+            messages.extend(ENVELOPE_PARAMS.iter().map(|param| {
+                Message::SetParam(
+                    ParamId::ModEnvelope(envelope_id, *param),
+                    param.get(envelope),
+                )
+            }));
+        }
+
+        messages.push(Message::ModRoute(ModRouteChange::Clear));
+        messages.extend(
+            self.modulation
+                .routes
+                .iter()
+                .map(|route| Message::ModRoute(ModRouteChange::Add(*route))),
+        );
+
+        messages
+    }
+}
+
+fn find_sample<'a>(samples: &'a [Sample], name: &str) -> Option<&'a Sample> {
+    samples.iter().find(|s| s.name == name)
+}
+
+/// Lists every preset file in the presets directory, sorted by name
+pub fn query_presets() -> Vec<PathBuf> {
+    let mut presets: Vec<PathBuf> = match fs::read_dir(PRESETS_PATH) {
+        Ok(dir) => dir
+            .filter_map(|d| d.ok().map(|d| d.path()))
+            .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+            .collect(),
+        Err(_) => vec![],
+    };
+    presets.sort();
+    presets
+}
+
+/// First preset_<n>.json in the presets directory that doesn't exist yet
+pub fn next_preset_path() -> PathBuf {
+    (1..)
+        .map(|n| Path::new(PRESETS_PATH).join(format!("preset_{}.json", n)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_filters_from_malformed_sections() {
+        let preset: Preset = serde_json::from_str(
+            r#"{
+                "oscillators": [{
+                    "effects": [
+                        {"IIRFilter": {"frequency": 0.0, "q": 0.0}},
+                        {"IIRFilter": {"frequency": 40000.0, "q": -1.0}},
+                        {"ModulatedFilter": {
+                            "base_frequency": -100.0,
+                            "filter": {"resonance": 0.0},
+                            "lfo": {"frequency": -5.0, "fade_in": -1.0},
+                            "lfo_depth": 100.0
+                        }}
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        for packet in &preset.oscillators[0].effects {
+            let mut effect = Effect::from_state_packet(packet, 22050.);
+            for (id, info) in effect.params().iter().enumerate() {
+                let value = effect.get_param(id).unwrap();
+                assert!(
+                    (info.min..=info.max).contains(&value),
+                    "{} is {} in {:?}",
+                    info.name,
+                    value,
+                    packet
+                );
+            }
+
+            let mut samples = [1.; 256];
+            effect.process_samples(0, &mut samples);
+            assert!(samples.iter().all(|s| s.is_finite()), "{:?}", packet);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use effects::ParamInfo;
+
+use crate::context::SampleClock;
+use crate::messages::Message;
+use crate::mixer::{Mixer, MixerStatePacket};
+use crate::modulation::ModRouteChange;
+use crate::params::ParamId;
+use crate::patch::build_mixer;
+use crate::synths::Sample;
+use crate::EngineError;
+
+/// The synthesizer behind a handful of calls, for embedding it in other tools.
+/// Renders interleaved stereo at the sample rate it was built for.
+pub struct Synth {
+    mixer: Mixer,
+}
+
+impl Synth {
+    pub fn new(mixer: Mixer) -> Synth {
+        Synth { mixer }
+    }
+
+    /// Plays the preset at the given path, or the default patch when there's none, with
+    /// up to voice_count notes at once
+    pub fn from_preset(
+        samples: &[Sample],
+        sample_rate: u32,
+        voice_count: usize,
+        preset: Option<&str>,
+    ) -> Result<Synth, EngineError> {
+        Ok(Synth::new(build_mixer(
+            samples,
+            sample_rate,
+            voice_count,
+            preset,
+        )?))
+    }
+
+    /// velocity is 0 to 1
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        self.mixer.handle_message(Message::NoteOn(note, velocity));
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        self.mixer.handle_message(Message::NoteOff(note));
+    }
+
+    /// Sets the parameter right away, see `params` for what there is
+    pub fn set_param(&mut self, id: ParamId, value: f32) {
+        self.mixer.set_param(id, value);
+    }
+
+    /// Adds, changes or removes routes of the modulation matrix
+    pub fn change_mod_route(&mut self, change: ModRouteChange) {
+        self.mixer.handle_message(Message::ModRoute(change));
+    }
+
+    /// Every parameter this synth's patch has, with its name, range and default
+    pub fn params(&self) -> Vec<(ParamId, ParamInfo)> {
+        self.mixer.get_state_packet().params()
+    }
+
+    /// Notes, parameters or anything else the mixer understands. Messages for parameters or
+    /// oscillators this patch doesn't have are ignored
+    pub fn handle_message(&mut self, message: Message) {
+        self.mixer.handle_message(message);
+    }
+
+    /// Plays the messages at their frames, counting from now
+    pub fn play_sequence(&mut self, events: Vec<(u64, Message)>) {
+        self.mixer.play_sequence(events);
+    }
+
+    /// Fills output with the next samples, interleaved by channel. Changes made since the
+    /// last call take effect at the start of output
+    pub fn process(&mut self, output: &mut [f32]) {
+        self.mixer.process_block(output);
+    }
+
+    /// Lets other threads time messages to the sample, see Message::Timed
+    pub fn get_sample_clock(&self) -> SampleClock {
+        self.mixer.get_sample_clock()
+    }
+
+    pub fn get_channels(&self) -> u16 {
+        self.mixer.channels
+    }
+
+    /// Samples rendered at a time, process can be given any amount but it's cheapest in
+    /// multiples of this
+    pub fn get_block_size(&self) -> usize {
+        self.mixer.context.block_size as usize
+    }
+
+    /// Allocates the buffers for blocks of the given size in samples, so it has to be called
+    /// before rendering from the audio thread starts
+    pub fn set_block_size(&mut self, block_size: usize) {
+        let sample_rate = self.mixer.context.sample_rate;
+        self.mixer.prepare(sample_rate, block_size as u32);
+    }
+
+    pub fn get_state_packet(&self) -> MixerStatePacket {
+        self.mixer.get_state_packet()
+    }
+
+    pub fn get_mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    pub fn get_mixer_mut(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+}
+
+impl From<Mixer> for Synth {
+    fn from(mixer: Mixer) -> Self {
+        Synth::new(mixer)
+    }
+}
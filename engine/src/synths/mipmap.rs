@@ -0,0 +1,68 @@
+//! Band-limited copies of a wavetable, one per octave
+//!
+//! Reading a table with a lot of harmonics at a high pitch pushes them over Nyquist, where they
+//! fold back down as inharmonic noise. Every level here keeps half the harmonics of the one
+//! before it, so the oscillator can always pick one that fits below Nyquist.
+
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+
+/// Builds the levels for a table of interleaved frames, each frame_len long.
+/// Level 0 is the table itself, the last level is down to the fundamental only.
+pub fn build_mipmaps(table: &[f32], frame_len: usize, channels: usize) -> Vec<Vec<f32>> {
+    let frame_size = frame_len / channels;
+    let top_harmonic = frame_size / 2;
+    if top_harmonic < 2 {
+        return vec![table.to_vec()];
+    }
+    let level_count = (top_harmonic as f32).log2() as usize + 1;
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(frame_size);
+    let inverse = planner.plan_fft_inverse(frame_size);
+
+    let mut input = forward.make_input_vec();
+    let mut spectrum = forward.make_output_vec();
+    let mut truncated = inverse.make_input_vec();
+    let mut output = inverse.make_output_vec();
+
+    let mut levels = vec![table.to_vec()];
+    levels.extend((1..level_count).map(|_| vec![0.; table.len()]));
+
+    for (frame_index, frame) in table.chunks(frame_len).enumerate() {
+        for channel in 0..channels {
+            let channel_samples = frame.iter().skip(channel).step_by(channels);
+            input
+                .iter_mut()
+                .zip(channel_samples)
+                .for_each(|(i, sample)| *i = *sample);
+            forward.process(&mut input, &mut spectrum).unwrap();
+
+            for (level, level_table) in levels.iter_mut().enumerate().skip(1) {
+                let max_harmonic = top_harmonic >> level;
+                truncated
+                    .iter_mut()
+                    .zip(spectrum.iter())
+                    .enumerate()
+                    .for_each(|(harmonic, (bin, value))| {
+                        *bin = if harmonic <= max_harmonic {
+                            *value
+                        } else {
+                            Complex::new(0., 0.)
+                        }
+                    });
+                inverse.process(&mut truncated, &mut output).unwrap();
+
+                // realfft doesn't normalise, the round trip scales everything by frame_size
+                level_table[frame_index * frame_len..(frame_index + 1) * frame_len]
+                    .iter_mut()
+                    .skip(channel)
+                    .step_by(channels)
+                    .zip(output.iter())
+                    .for_each(|(out, sample)| *out = sample / frame_size as f32);
+            }
+        }
+    }
+
+    levels
+}
@@ -0,0 +1,814 @@
+#![allow(dead_code)]
+
+mod mipmap;
+
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+use hound::{SampleFormat, WavReader, WavSpec};
+use itertools::Itertools;
+
+use effects::adsr::{ADSREnvelope, ADSR};
+use effects::filters::IIRLowPassFilter;
+use effects::smoothing::{SmoothedValue, Smoothing};
+use effects::{Effect, EffectStatePacket, Processor};
+
+use crate::context::DEFAULT_BLOCK_SIZE;
+use crate::params::OscParam;
+use crate::synths::mipmap::build_mipmaps;
+use crate::EngineError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub path: String,
+    // Samples per frame for files without a clm chunk, the whole file is a single frame when None
+    pub frame_size: Option<usize>,
+    // Mix stereo files down to mono instead of keeping both channels
+    pub downmix: bool,
+}
+
+/// Lists every .wav file in dir
+pub fn query_samples(
+    dir: &str,
+    frame_size: Option<usize>,
+    downmix: bool,
+) -> Result<Vec<Sample>, EngineError> {
+    let entries = std::fs::read_dir(dir).map_err(|source| EngineError::Io {
+        path: dir.to_string(),
+        source,
+    })?;
+
+    let samples: Vec<Sample> = entries
+        .filter_map(|d| d.ok().map(|d| d.path()))
+        .filter(|p| {
+            p.extension()
+                .map(|e| e.eq_ignore_ascii_case("wav"))
+                .unwrap_or(false)
+        })
+        .map(|p| Sample {
+            name: p
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: p.to_string_lossy().to_string(),
+            frame_size,
+            downmix,
+        })
+        .collect();
+
+    if samples.is_empty() {
+        return Err(EngineError::NoWavetables(dir.to_string()));
+    }
+    Ok(samples)
+}
+
+pub struct Wavetable {
+    // All frames back to back
+    pub sample_table: Vec<f32>,
+    // Band-limited copies of sample_table, one per octave with levels[0] being sample_table itself
+    pub levels: Vec<Vec<f32>>,
+    // Length of a single frame in sample_table, including every channel
+    pub frame_len: usize,
+    pub frame_count: usize,
+
+    // Buffer of samples from .wav file
+    pub spec: WavSpec,
+    pub sample: Sample,
+}
+
+impl Wavetable {
+    /// Loads a wav file and splits it up into frames, using the frame size from Serum's clm chunk
+    /// when there's one.
+    /// Tables are kept at their own sample rate, resampling would smear frames into each other.
+    /// They're always stored as interleaved stereo, to match the mixer's output.
+    pub fn create_wavetable(sample: Sample) -> Result<Wavetable, EngineError> {
+        let data = std::fs::read(&sample.path).map_err(|source| EngineError::Io {
+            path: sample.path.clone(),
+            source,
+        })?;
+        let frame_size = read_clm_frame_size(&data).or(sample.frame_size);
+
+        let invalid_wav = |source| EngineError::InvalidWav {
+            path: sample.path.clone(),
+            source,
+        };
+        let reader = WavReader::new(Cursor::new(data)).map_err(invalid_wav)?;
+        let mut input_wav_spec = reader.spec();
+
+        let mut fsamples = read_samples(reader).map_err(invalid_wav)?;
+        if fsamples.is_empty() {
+            return Err(EngineError::EmptyWavetable(sample.path));
+        }
+
+        // Anything that isn't played in stereo gets mixed down to mono first
+        let channels = input_wav_spec.channels as usize;
+        if channels > 2 || (channels == 2 && sample.downmix) {
+            fsamples = fsamples
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect();
+            input_wav_spec.channels = 1;
+        }
+
+        //Double the mono channel to stereo via interleaving manually
+        if input_wav_spec.channels == 1 {
+            input_wav_spec.channels = 2;
+
+            let sample_iter1 = fsamples.iter();
+            let sample_iter2 = fsamples.iter();
+            fsamples = sample_iter1
+                .interleave(sample_iter2)
+                .copied()
+                .collect::<Vec<f32>>();
+        }
+
+        let frame_len = match frame_size {
+            Some(size) if size * (input_wav_spec.channels as usize) <= fsamples.len() => {
+                size * input_wav_spec.channels as usize
+            }
+            _ => fsamples.len(),
+        };
+        let frame_count = fsamples.len() / frame_len;
+        // Drop the leftovers of an incomplete last frame
+        fsamples.truncate(frame_len * frame_count);
+
+        let levels = build_mipmaps(&fsamples, frame_len, input_wav_spec.channels as usize);
+
+        Ok(Wavetable {
+            sample_table: fsamples,
+            levels,
+            frame_len,
+            frame_count,
+            spec: input_wav_spec,
+            sample,
+        })
+    }
+
+    #[inline(always)]
+    pub fn get_num_samples(&self) -> usize {
+        self.sample_table.len()
+    }
+
+    /// Samples per frame of a single channel, which is also the length of one cycle
+    #[inline(always)]
+    pub fn get_frame_size(&self) -> usize {
+        self.frame_len / self.spec.channels as usize
+    }
+
+    #[inline(always)]
+    pub fn get_level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    #[inline(always)]
+    pub fn get_frame(&self, level: usize, frame: usize) -> &[f32] {
+        &self.levels[level][frame * self.frame_len..(frame + 1) * self.frame_len]
+    }
+
+    /// Band-limited level to read for the given frequency in cycles per output frame,
+    /// fractions crossfade between two levels
+    pub fn get_mipmap_position(&self, cycle_rate: f32) -> f32 {
+        let top_harmonic = (self.get_frame_size() / 2) as f32 * cycle_rate;
+        // Octaves the top harmonic of the full table sits above Nyquist, each level drops one
+        let octaves_over = (top_harmonic / 0.5).log2();
+
+        // One level of headroom, so neither of the two levels being crossfaded goes over Nyquist.
+        // Costs up to an octave of the highest harmonics, which the 15k low pass mostly eats anyway
+        let max_level = (self.get_level_count() - 1) as f32;
+        (octaves_over + 1.).clamp(0., max_level)
+    }
+
+    /// Reads one channel at index (in samples of a single cycle), blending between mipmap levels
+    /// and between the two frames closest to position
+    #[inline(always)]
+    pub fn read(&self, mipmap_position: f32, position: f32, index: f32, channel: usize) -> f32 {
+        let frame_size = self.get_frame_size();
+        let index0 = (index as usize).min(frame_size - 1);
+        let index1 = if index0 + 1 == frame_size {
+            0
+        } else {
+            index0 + 1
+        };
+        let frac = index - (index0 as f32);
+
+        let channels = self.spec.channels as usize;
+        let index0 = index0 * channels + channel;
+        let index1 = index1 * channels + channel;
+
+        let level0 = mipmap_position as usize;
+        let level1 = (level0 + 1).min(self.get_level_count() - 1);
+        let level_frac = mipmap_position - (level0 as f32);
+
+        let value0 = self.read_level(level0, position, index0, index1, frac);
+        let value1 = self.read_level(level1, position, index0, index1, frac);
+
+        value0 + level_frac * (value1 - value0)
+    }
+
+    #[inline(always)]
+    fn read_level(
+        &self,
+        level: usize,
+        position: f32,
+        index0: usize,
+        index1: usize,
+        frac: f32,
+    ) -> f32 {
+        let frame_position = position * (self.frame_count - 1) as f32;
+        let frame0 = frame_position as usize;
+        let frame1 = (frame0 + 1).min(self.frame_count - 1);
+        let frame_frac = frame_position - (frame0 as f32);
+
+        let value0 = interpolate(self.get_frame(level, frame0), index0, index1, frac);
+        let value1 = interpolate(self.get_frame(level, frame1), index0, index1, frac);
+
+        value0 + frame_frac * (value1 - value0)
+    }
+}
+
+/// Decodes every sample into an f32 between -1 and 1, whatever the format of the file
+fn read_samples<R: Read>(reader: WavReader<R>) -> Result<Vec<f32>, hound::Error> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>().collect(),
+        SampleFormat::Int => {
+            let scale = 1. / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect()
+        }
+    }
+}
+
+/// Frame size from the clm chunk Serum writes into its wavetables, the chunk's text
+/// starts with e.g. "<!>2048" for 2048 sample frames
+fn read_clm_frame_size(data: &[u8]) -> Option<usize> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = u32::from_le_bytes([
+            data[offset + 4],
+            data[offset + 5],
+            data[offset + 6],
+            data[offset + 7],
+        ]) as usize;
+        let body = offset + 8;
+
+        if id == b"clm " {
+            let chunk = data.get(body..(body + size).min(data.len()))?;
+            let text = String::from_utf8_lossy(chunk);
+            let digits: String = text
+                .strip_prefix("<!>")?
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            return digits.parse().ok().filter(|&size| size > 0);
+        }
+
+        // Chunks are padded to an even length
+        offset = body + size + (size & 1);
+    }
+
+    None
+}
+
+/// Time it takes to fade from one wavetable into the next, in seconds
+pub const DEFAULT_CROSSFADE_TIME: f32 = 0.05;
+
+#[derive(Clone)]
+pub struct OscStatePacket {
+    pub name: String,
+    pub gain: f32,
+    pub frequency: f32,
+    pub position: f32,
+    pub coarse: f32,
+    pub fine: f32,
+
+    pub adsr: ADSR,
+    pub effects: Vec<EffectStatePacket>,
+}
+
+impl OscStatePacket {
+    pub fn get_param(&self, param: OscParam) -> f32 {
+        match param {
+            OscParam::Gain => self.gain,
+            OscParam::Position => self.position,
+            OscParam::Coarse => self.coarse,
+            OscParam::Fine => self.fine,
+        }
+    }
+
+    pub fn set_param(&mut self, param: OscParam, value: f32) {
+        match param {
+            OscParam::Gain => self.gain = value,
+            OscParam::Position => self.position = value,
+            OscParam::Coarse => self.coarse = value,
+            OscParam::Fine => self.fine = value,
+        }
+    }
+}
+
+// Cloning an oscillator is how voices get their own phase, envelope and effect state,
+// the wavetable itself is shared between all of them
+#[derive(Clone)]
+pub struct Oscillator {
+    sample_rate: f32,
+    gain: SmoothedValue,
+    frequency: f32,
+    // 0 to 1, scanning from the first frame of the wavetable to the last
+    position: f32,
+    // Tuning on top of the played note, in semitones and cents
+    coarse: f32,
+    fine: f32,
+    // Position within the cycle, in frames of the table
+    current_index: f32,
+    table_delta: f32,
+    // Channel of the interleaved table the next sample is read from
+    channel: usize,
+    // Gain and envelope of the current frame, the same for all of its channels
+    frame_level: f32,
+    // Which band-limited level of the wavetable to read, fractions crossfade between two levels
+    mipmap_position: f32,
+
+    pub wavetable: Arc<Wavetable>,
+    // Table that was playing before the last change, faded out over crossfade_length frames
+    previous_wavetable: Option<Arc<Wavetable>>,
+    previous_mipmap_position: f32,
+    // Seconds, crossfade_length is the same in frames
+    crossfade_time: f32,
+    crossfade_length: u32,
+    crossfade_remaining: u32,
+
+    pub effects: Vec<Effect>,
+
+    pub envelope: ADSREnvelope,
+}
+
+impl Oscillator {
+    pub fn new(gain: f32, frequency: f32, wavetable: Wavetable, sample_rate: f32) -> Oscillator {
+        let mut osc = Oscillator {
+            sample_rate,
+            gain: SmoothedValue::new(gain, Smoothing::Linear),
+            frequency,
+            position: 0.,
+            coarse: 0.,
+            fine: 0.,
+            wavetable: Arc::new(wavetable),
+            previous_wavetable: None,
+            previous_mipmap_position: 0.,
+            crossfade_time: DEFAULT_CROSSFADE_TIME,
+            crossfade_length: 0,
+            crossfade_remaining: 0,
+
+            current_index: 0.,
+            table_delta: 0.,
+            channel: 0,
+            frame_level: 0.,
+            mipmap_position: 0.,
+            effects: vec![],
+            envelope: ADSREnvelope::new(ADSR::default()),
+        };
+
+        // Kept below Nyquist, which is under 15kHz at low sample rates
+        osc.add_effect(Effect::IIRFilter(IIRLowPassFilter::new_low_pass(
+            sample_rate,
+            15000_f32.min(sample_rate * 0.45),
+            1.,
+        )));
+        osc.prepare(sample_rate, DEFAULT_BLOCK_SIZE as usize);
+        osc
+    }
+
+    /// Has to be called whenever the sample rate changes, before rendering anything.
+    /// max_block is the most samples that will be rendered at once
+    pub fn prepare(&mut self, sample_rate: f32, max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.crossfade_length = (self.crossfade_time * sample_rate) as u32;
+        self.crossfade_remaining = self.crossfade_remaining.min(self.crossfade_length);
+        self.gain.prepare(sample_rate);
+
+        self.envelope.prepare(sample_rate, max_block);
+        for effect in &mut self.effects {
+            effect.prepare(sample_rate, max_block);
+        }
+        self.update_table_delta();
+    }
+
+    /// Playing notes keep going, crossfading from the current table into the new one
+    pub fn change_wavetable(&mut self, new_wavetable: Arc<Wavetable>) {
+        // Carry on from the same spot in the cycle, whatever the new table's frame size is
+        let phase = self.current_index / self.wavetable.get_frame_size() as f32;
+
+        let previous = std::mem::replace(&mut self.wavetable, new_wavetable);
+        if self.envelope.is_active() && self.crossfade_length > 0 {
+            // A crossfade that's still going gets cut short, the table being faded in is
+            // what's heard the most by now anyway
+            self.previous_wavetable = Some(previous);
+            self.crossfade_remaining = self.crossfade_length;
+        } else {
+            self.previous_wavetable = None;
+            self.crossfade_remaining = 0;
+        }
+
+        self.current_index = phase * self.wavetable.get_frame_size() as f32;
+        self.update_table_delta();
+    }
+
+    pub fn set_crossfade_time(&mut self, seconds: f32) {
+        self.crossfade_time = seconds.max(0.);
+        self.crossfade_length = (self.crossfade_time * self.sample_rate) as u32;
+    }
+
+    /// Channels interleaved in the sample clock, for effects that keep time by it
+    pub fn set_clock_channels(&mut self, channels: u16) {
+        for effect in &mut self.effects {
+            effect.set_channels(channels);
+        }
+    }
+
+    /// How long gain and effect parameter changes take to ramp to their new values
+    pub fn set_smoothing_time(&mut self, seconds: f32) {
+        self.gain.set_ramp_time(seconds);
+        for effect in &mut self.effects {
+            effect.set_smoothing_time(seconds);
+        }
+    }
+
+    pub fn get_state_packet(&self) -> OscStatePacket {
+        let effect_packets = self
+            .effects
+            .iter()
+            .map(|e| e.get_state_packet())
+            .collect_vec();
+
+        OscStatePacket {
+            name: self.wavetable.sample.name.clone(),
+            gain: self.gain.get_target(),
+            frequency: self.frequency,
+            position: self.position,
+            coarse: self.coarse,
+            fine: self.fine,
+            adsr: self.envelope.adsr_values,
+            effects: effect_packets,
+        }
+    }
+
+    pub fn add_effect(&mut self, effect: Effect) {
+        self.effects.push(effect);
+    }
+
+    pub fn reset(&mut self) {
+        self.current_index = 0.;
+        self.channel = 0;
+        self.envelope.reset();
+        self.effects.iter_mut().for_each(|e| e.reset());
+    }
+
+    /// velocity is 0 to 1, the envelope decides what it does
+    pub fn trigger(&mut self, sample_clock: u64, velocity: f32) {
+        // Notes start on frame boundaries, but the voice might have stopped halfway through one
+        self.channel = 0;
+        self.envelope.trigger(sample_clock, velocity);
+        self.effects
+            .iter_mut()
+            .for_each(|e| e.trigger(sample_clock));
+    }
+
+    /// Tempo in BPM for effects that sync to it
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.effects.iter_mut().for_each(|e| e.set_tempo(bpm));
+    }
+
+    pub fn release(&mut self, sample_clock: u64) {
+        self.envelope.release(sample_clock);
+    }
+
+    #[inline(always)]
+    pub fn get_next_sample(&mut self, sample_time: u64) -> f32 {
+        // Gain and envelope move on once per frame, like the phase
+        if self.channel == 0 {
+            self.frame_level =
+                self.gain.get_next_value() * self.envelope.get_next_sample(sample_time);
+        }
+
+        let mut current_sample = self.wavetable.read(
+            self.mipmap_position,
+            self.position,
+            self.current_index,
+            self.channel,
+        );
+
+        if let Some(previous) = &self.previous_wavetable {
+            // Same spot in the cycle of the previous table
+            let index = self.current_index / self.wavetable.get_frame_size() as f32
+                * previous.get_frame_size() as f32;
+            let previous_sample = previous.read(
+                self.previous_mipmap_position,
+                self.position,
+                index,
+                self.channel,
+            );
+
+            let fade = self.crossfade_remaining as f32 / self.crossfade_length as f32;
+            current_sample += fade * (previous_sample - current_sample);
+        }
+
+        // The mixer asks for one sample per channel, the phase only moves on once all
+        // channels of the current frame have been read
+        self.channel += 1;
+        if self.channel == self.wavetable.spec.channels as usize {
+            self.channel = 0;
+            self.current_index += self.table_delta;
+
+            // Wrap by the full frame size, so every cycle is exactly one frame long. Notes
+            // pitched above the sample rate skip more than a whole cycle at a time
+            let frame_size = self.wavetable.get_frame_size() as f32;
+            if self.current_index >= frame_size {
+                self.current_index = self.current_index.rem_euclid(frame_size);
+            }
+
+            if self.crossfade_remaining > 0 {
+                self.crossfade_remaining -= 1;
+                if self.crossfade_remaining == 0 {
+                    // Only drops a reference, the loader frees the table
+                    self.previous_wavetable = None;
+                }
+            }
+        }
+
+        current_sample * self.frame_level
+    }
+
+    /// Overwrites chunk with the next samples, the first one being at sample_clock_start
+    pub fn write_next_chunk(&mut self, chunk: &mut [f32], sample_clock_start: u64) {
+        for (i, sample) in chunk.iter_mut().enumerate() {
+            *sample = self.get_next_sample(sample_clock_start + i as u64);
+        }
+    }
+
+    pub fn get_channels(&self) -> u16 {
+        self.wavetable.spec.channels
+    }
+
+    /// Frequency actually played, after the oscillator's coarse and fine tuning
+    pub fn get_tuned_frequency(&self) -> f32 {
+        self.frequency * 2_f32.powf((self.coarse + self.fine / 100.) / 12.)
+    }
+
+    fn update_table_delta(&mut self) {
+        // Every frame is exactly one cycle and the phase moves once per output frame,
+        // so at a delta of 1 one cycle lasts frame_size output frames.
+        // The table's own sample rate doesn't matter, only its cycle length does
+        let cycle_length = self.wavetable.get_frame_size() as f32;
+        self.table_delta = self.get_tuned_frequency() * cycle_length / self.sample_rate;
+        self.update_mipmap_position();
+    }
+
+    fn update_mipmap_position(&mut self) {
+        // Cycles per output frame
+        let cycle_rate = self.get_tuned_frequency() / self.sample_rate;
+
+        self.mipmap_position = self.wavetable.get_mipmap_position(cycle_rate);
+        if let Some(previous) = &self.previous_wavetable {
+            self.previous_mipmap_position = previous.get_mipmap_position(cycle_rate);
+        }
+    }
+
+    // fn update_low_pass_filter(&mut self) {
+    // Internal low pass filter we use for clamping is always index 0, since we always add it first
+    // TODO: No longer used perhaps since we're no longer dynamically changing its frq based on osc frq?
+    // if let EffectEnum::IIRFilter(e) = &mut self.effects[0] {
+    //     e.set_frequency(15_000.);
+    // }
+    // }
+
+    /// Ramps to the new gain over the smoothing time
+    pub fn set_gain(&mut self, new_gain: f32) {
+        self.gain.set_target(new_gain);
+    }
+
+    pub fn set_position(&mut self, new_position: f32) {
+        self.position = new_position.clamp(0., 1.);
+    }
+
+    pub fn set_coarse(&mut self, semitones: f32) {
+        self.coarse = semitones;
+        self.update_table_delta();
+    }
+
+    pub fn set_fine(&mut self, cents: f32) {
+        self.fine = cents;
+        self.update_table_delta();
+    }
+
+    pub fn set_frequency(&mut self, new_frequency: f32) {
+        self.frequency = new_frequency;
+        // self.update_low_pass_filter();
+        self.update_table_delta();
+    }
+
+    /// Gain is the value being ramped to
+    pub fn get_param(&self, param: OscParam) -> f32 {
+        match param {
+            OscParam::Gain => self.gain.get_target(),
+            OscParam::Position => self.position,
+            OscParam::Coarse => self.coarse,
+            OscParam::Fine => self.fine,
+        }
+    }
+
+    pub fn set_param(&mut self, param: OscParam, value: f32) {
+        match param {
+            OscParam::Gain => self.set_gain(value),
+            OscParam::Position => self.set_position(value),
+            OscParam::Coarse => self.set_coarse(value),
+            OscParam::Fine => self.set_fine(value),
+        }
+    }
+
+    /// Same as set_param, but the gain jumps to the value instead of ramping to it
+    pub fn set_param_immediate(&mut self, param: OscParam, value: f32) {
+        match param {
+            OscParam::Gain => self.gain.set_immediate(value),
+            _ => self.set_param(param, value),
+        }
+    }
+}
+
+#[inline(always)]
+fn interpolate(frame: &[f32], index0: usize, index1: usize, frac: f32) -> f32 {
+    frame[index0] + frac * (frame[index1] - frame[index0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EngineContext;
+    use crate::messages::Message;
+    use crate::mixer::Mixer;
+    use crate::params::ParamId;
+    use hound::SampleFormat;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    // A single cycle of a sine, so the rendered fundamental is easy to measure
+    fn sine_wavetable(frame_size: usize) -> Wavetable {
+        let sample_table: Vec<f32> = (0..frame_size)
+            .flat_map(|i| {
+                let value = (i as f32 / frame_size as f32 * std::f32::consts::TAU).sin();
+                vec![value, value]
+            })
+            .collect();
+        let levels = build_mipmaps(&sample_table, frame_size * 2, 2);
+
+        Wavetable {
+            sample_table,
+            levels,
+            frame_len: frame_size * 2,
+            frame_count: 1,
+            spec: WavSpec {
+                channels: 2,
+                sample_rate: SAMPLE_RATE,
+                bits_per_sample: 32,
+                sample_format: SampleFormat::Float,
+            },
+            sample: Sample {
+                name: "sine".to_string(),
+                path: String::new(),
+                frame_size: None,
+                downmix: false,
+            },
+        }
+    }
+
+    fn sine_mixer() -> Mixer {
+        sine_mixer_at(SAMPLE_RATE)
+    }
+
+    fn sine_mixer_at(sample_rate: u32) -> Mixer {
+        let mut osc = Oscillator::new(1., 440., sine_wavetable(2048), sample_rate as f32);
+        // Straight to full level, so the very first cycles count too
+        osc.envelope.adsr_values = ADSR {
+            attack: 0.,
+            decay: 0.,
+            sustain: 1.,
+            ..ADSR::default()
+        };
+        Mixer::new(vec![osc], 1, EngineContext::new(sample_rate))
+    }
+
+    // Renders a second of the note at the mixer's sample rate and measures the frequency of the left channel from the
+    // first to the last upward zero crossing
+    fn rendered_frequency(mixer: &mut Mixer, note: u8) -> f32 {
+        let sample_rate = mixer.context.sample_rate;
+        mixer.handle_message(Message::NoteOn(note, 1.));
+        let mut output = vec![0.; sample_rate as usize * 2];
+        mixer.process_block(&mut output);
+
+        let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+        let crossings: Vec<f32> = left
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0. && w[1] >= 0.)
+            .map(|(i, w)| i as f32 + w[0] / (w[0] - w[1]))
+            .collect();
+
+        let first = crossings[0];
+        let last = crossings[crossings.len() - 1];
+        (crossings.len() - 1) as f32 / (last - first) * sample_rate
+    }
+
+    fn assert_frequency(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.1,
+            "rendered {} Hz instead of {} Hz",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn a4_plays_at_440_hz() {
+        assert_frequency(rendered_frequency(&mut sine_mixer(), 69), 440.);
+    }
+
+    #[test]
+    fn renders_at_low_sample_rates() {
+        let mut mixer = sine_mixer_at(22050);
+        assert_frequency(rendered_frequency(&mut mixer, 69), 440.);
+    }
+
+    #[test]
+    fn coarse_tuning_shifts_by_semitones() {
+        let mut mixer = sine_mixer();
+        mixer.handle_message(Message::SetParam(ParamId::Osc(0, OscParam::Coarse), 12.));
+        assert_frequency(rendered_frequency(&mut mixer, 69), 880.);
+
+        let mut mixer = sine_mixer();
+        mixer.handle_message(Message::SetParam(ParamId::Osc(0, OscParam::Coarse), -7.));
+        assert_frequency(
+            rendered_frequency(&mut mixer, 69),
+            440. * 2_f32.powf(-7. / 12.),
+        );
+    }
+
+    #[test]
+    fn fine_tuning_shifts_by_cents() {
+        let mut mixer = sine_mixer();
+        mixer.handle_message(Message::SetParam(ParamId::Osc(0, OscParam::Fine), 50.));
+        assert_frequency(
+            rendered_frequency(&mut mixer, 69),
+            440. * 2_f32.powf(0.5 / 12.),
+        );
+    }
+
+    #[test]
+    fn master_tune_moves_every_note() {
+        let mut mixer = sine_mixer().with_master_tune(432.);
+        assert_frequency(rendered_frequency(&mut mixer, 69), 432.);
+
+        let mut mixer = sine_mixer();
+        mixer.handle_message(Message::MasterTune(415.));
+        assert_frequency(rendered_frequency(&mut mixer, 57), 207.5);
+    }
+
+    #[test]
+    fn envelope_runs_by_frames() {
+        let mut osc = Oscillator::new(1., 440., sine_wavetable(2048), SAMPLE_RATE as f32);
+        osc.envelope.adsr_values.attack = 0.1;
+        osc.trigger(0, 1.);
+
+        // Half the attack, in stereo samples
+        let frames = SAMPLE_RATE as u64 / 20;
+        for i in 0..frames * 2 {
+            osc.get_next_sample(i);
+        }
+        let level = osc.envelope.get_last_value();
+        assert!((level - 0.5).abs() < 1e-3, "envelope is at {}", level);
+    }
+
+    #[test]
+    fn phase_stays_in_the_cycle_above_the_sample_rate() {
+        let mut osc = Oscillator::new(1., 440., sine_wavetable(2048), SAMPLE_RATE as f32);
+        osc.set_coarse(24.);
+        osc.set_frequency(crate::tuning::midi_note_to_frequency(127, 440.));
+        osc.trigger(0, 1.);
+
+        for i in 0..SAMPLE_RATE as u64 {
+            let sample = osc.get_next_sample(i);
+            assert!(
+                sample.is_finite() && sample.abs() <= 1.,
+                "sample {} is {}",
+                i,
+                sample
+            );
+        }
+        assert!(osc.current_index < osc.wavetable.get_frame_size() as f32);
+    }
+}
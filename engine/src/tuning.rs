@@ -0,0 +1,7 @@
+/// Frequency of A4 unless told otherwise
+pub const DEFAULT_MASTER_TUNE: f32 = 440.;
+
+/// Equal temperament, with master_tune being the frequency of A4
+pub fn midi_note_to_frequency(note: u8, master_tune: f32) -> f32 {
+    master_tune * 2_f32.powf((note as i16 - 69) as f32 / 12.)
+}